@@ -1,16 +1,19 @@
 use core::panic;
-use std::{collections::VecDeque, sync::Mutex};
+use std::sync::Mutex;
 
-use avro_rs::{Reader, types::Value};
 use cpal::{OutputCallbackInfo, SampleFormat, SampleRate, Stream, SupportedStreamConfigRange, traits::{DeviceTrait, HostTrait, StreamTrait}};
 use eyre::Result;
+use futures::StreamExt;
 
-use emuka_server::{audio::{SAMPLE_RATE, StereoSample, VecStereoWrapper}, server::api::v1::api::{AUDIO_DATA_API_SCHEMA, AudioRegisterApi}};
+use emuka_server::{audio::{SAMPLE_RATE, StereoSample, VecStereoWrapper, stream::AudioBlockReader}, server::api::v1::api::AudioRegisterApi};
 use lazy_static::lazy_static;
 use tokio::time;
+use uuid::Uuid;
+
+use crate::jitter::{self, JitterBuffer};
 
 lazy_static! {
-    pub(crate) static ref SAMPLES: Mutex<VecDeque<StereoSample>> = Mutex::new(VecDeque::with_capacity((SAMPLE_RATE * 20) as usize));
+    pub(crate) static ref SAMPLES: Mutex<JitterBuffer> = Mutex::new(JitterBuffer::new(jitter::target_latency()));
 }
 
 pub fn init_audio_stream() -> Stream {
@@ -77,91 +80,95 @@ T: cpal::Sample {
 
     let mut lock = SAMPLES.lock().unwrap();
     let samples = &mut *lock;
+    samples.update_rate();
 
     for frame in data.chunks_mut(channels) {
-        let next = samples.pop_front();
-        match next {
-            Some(value) => {
-                let left: T = cpal::Sample::from::<i16>(&value.left);
-                let right: T = cpal::Sample::from::<i16>(&value.right);
-                let mut frame_iter = frame.iter_mut();
-                *frame_iter.next().unwrap() = left;
-                *frame_iter.next().unwrap() = right;
-            },
-            None => break
-        }
+        // Underruns play silence rather than whatever was left in the buffer.
+        let value = samples.next_sample().unwrap_or(StereoSample {left: 0, right: 0});
+        let left: T = cpal::Sample::from::<i16>(&value.left);
+        let right: T = cpal::Sample::from::<i16>(&value.right);
+        let mut frame_iter = frame.iter_mut();
+        *frame_iter.next().unwrap() = left;
+        *frame_iter.next().unwrap() = right;
     }
 }
 
 
 
-fn decode_avro_data_samples(avro_data: &[u8]) -> Vec<u8> {
-    let reader = Reader::with_schema(&AUDIO_DATA_API_SCHEMA, &avro_data[..]).unwrap();
-    for record in reader {
-        match record.unwrap() {
-            Value::Record(fields) => {
-                let bytes = &fields.get(0).unwrap().1;
-                match bytes {
-                    Value::Bytes(data) => {
-                        return data.clone()
-                    },
-                    _ => panic!()
-                }
-            },
-            _  => panic!()
-        }
-    }
-    return Vec::new();
+async fn register_audio(base: &str) -> Result<Uuid> {
+    let register = reqwest::get(&format!("{}/api/v1/audio/register", base))
+        .await?
+        .json::<AudioRegisterApi>()
+        .await?;
+
+    Ok(register.id)
 }
 
-fn write_encoded_samples(avro_data: &[u8]) {
-    let decoded = decode_avro_data_samples(avro_data);
-    let wrapper = VecStereoWrapper::from(decoded);
+/// Plays everything streamed from the queue `audio_id`, until the connection
+/// drops. Returns `false` if the queue doesn't exist anymore.
+async fn stream_audio(base: &str, audio_id: Uuid) -> Result<bool> {
+    let response = reqwest::get(&format!("{}/api/v1/audio/stream/{}", base, audio_id)).await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(false);
+    }
+
+    let mut body = response.error_for_status()?.bytes_stream();
+    let mut reader = AudioBlockReader::new();
+    let mut expected: Option<u64> = None;
 
-    println!("{:?}", wrapper.inner.as_ref().unwrap().len());
+    while let Some(chunk) = body.next().await {
+        reader.push(&chunk?);
 
-    if let Some(data) = wrapper.inner {
-        let mut lock = SAMPLES.lock().unwrap();
-        lock.extend(data.into_iter());
+        while let Some(block) = reader.next_block() {
+            if let Some(expected) = expected {
+                if block.position != expected {
+                    println!("Lost {} samples before block {}", block.position.saturating_sub(expected), block.sequence);
+                }
+            }
+            expected = Some(block.end());
+
+            // Registered with the default format, interleaved stereo i16.
+            if let Some(samples) = VecStereoWrapper::from(block.data).inner {
+                let underruns = {
+                    let mut lock = SAMPLES.lock().unwrap();
+                    lock.push(samples);
+                    lock.take_underruns()
+                };
+
+                if underruns > 0 {
+                    println!("Audio underruns: {}", underruns);
+                }
+            }
+        }
     }
+
+    Ok(true)
 }
 
 pub async fn init_audio_requests(base: String) -> Result<()> {
-    let audio_id = reqwest::get(&format!("{}/api/v1/audio/register", base))
-        .await?
-        .json::<AudioRegisterApi>()
-        .await?
-        .id;
-    
     tokio::spawn(async move {
-        let mut interval = time::interval(time::Duration::from_millis(10));
-        
+        // Kept across reconnections, so a dropped connection doesn't leave
+        // an unread queue behind on the server.
+        let mut audio_id: Option<Uuid> = None;
+
         loop {
-            interval.tick().await;
-
-            let response =
-            reqwest::get(&format!("{}/api/v1/audio/get/{}", base, audio_id))
-            .await;
-
-            match response {
-                Err(err) => {
-                    println!("{}", err);
-                    continue;
-                },
-                Ok(response) => {
-                    let data = response.bytes().await;
-                    match data {
-                        Err(err) => {
-                            println!("{}", err);
-                            continue;
-                        },
-                        Ok(data) => {
-                            write_encoded_samples(&data);
-                        }
-                    }
+            if audio_id.is_none() {
+                match register_audio(&base).await {
+                    Ok(id) => audio_id = Some(id),
+                    Err(err) => println!("{}", err)
                 }
-            };
-            
+            }
+
+            if let Some(id) = audio_id {
+                match stream_audio(&base, id).await {
+                    // Evicted, or the server restarted.
+                    Ok(false) => audio_id = None,
+                    Ok(true) => (),
+                    Err(err) => println!("{}", err)
+                }
+            }
+
+            time::sleep(time::Duration::from_secs(1)).await;
         }
     });
 
@@ -0,0 +1,245 @@
+use std::{collections::VecDeque, time::Duration};
+
+use emuka_server::audio::{SAMPLE_RATE, StereoSample};
+
+static TARGET_LATENCY_VARIABLE: &str = "EMUKA_AUDIO_LATENCY_MS";
+const DEFAULT_TARGET_LATENCY: Duration = Duration::from_millis(60);
+
+/// Past this many times the target, the excess is dropped outright instead
+/// of being caught up on by resampling.
+const MAX_LATENCY_FACTOR: usize = 4;
+/// Playback speed never strays further than this from 1, which keeps the
+/// pitch change inaudible.
+const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+/// Weight of each new fill level measurement, measured once per callback.
+const FILL_SMOOTHING: f64 = 0.05;
+/// How fast the steady clock drift is learned, per callback. Without it a
+/// constant drift would keep the fill level away from the target.
+const DRIFT_GAIN: f64 = 0.00002;
+
+/// Target latency, which can be set in milliseconds with the
+/// `EMUKA_AUDIO_LATENCY_MS` environment variable.
+pub fn target_latency() -> Duration {
+    match std::env::var(TARGET_LATENCY_VARIABLE).ok().and_then(|value| value.parse::<u64>().ok()) {
+        Some(milliseconds) if milliseconds > 0 => Duration::from_millis(milliseconds),
+        _ => DEFAULT_TARGET_LATENCY
+    }
+}
+
+/// Holds received samples around a target fill level. The server and the
+/// sound card clocks never quite agree, so playback is sped up or slowed
+/// down a little to keep the level steady.
+#[derive(Debug)]
+pub struct JitterBuffer {
+    samples: VecDeque<StereoSample>,
+    /// Target fill level, in samples.
+    target: usize,
+    /// Smoothed fill level.
+    fill: f64,
+    /// Source samples consumed per output sample.
+    rate: f64,
+    /// Learned rate offset between the two clocks.
+    drift: f64,
+    /// Position between the first two samples.
+    phase: f64,
+    /// Filling back up to the target after an underrun, or before starting.
+    buffering: bool,
+    /// Underruns since the last `take_underruns`. Counted rather than
+    /// printed, as the output callback must not block on I/O.
+    underruns: u64
+}
+
+impl JitterBuffer {
+    pub fn new(target_latency: Duration) -> Self {
+        let target = ((target_latency.as_secs_f64() * SAMPLE_RATE as f64) as usize).max(2);
+
+        Self {
+            samples: VecDeque::with_capacity(target * MAX_LATENCY_FACTOR),
+            target,
+            fill: target as f64,
+            rate: 1.0,
+            drift: 0.0,
+            phase: 0.0,
+            buffering: true,
+            underruns: 0
+        }
+    }
+
+    pub fn take_underruns(&mut self) -> u64 {
+        std::mem::replace(&mut self.underruns, 0)
+    }
+
+    pub fn push<I: IntoIterator<Item = StereoSample>>(&mut self, samples: I) {
+        self.samples.extend(samples);
+
+        if self.samples.len() > self.target * MAX_LATENCY_FACTOR {
+            let excess = self.samples.len() - self.target;
+            self.samples.drain(..excess);
+            self.fill = self.target as f64;
+        }
+    }
+
+    /// Adjusts the playback rate from the current fill level. Should be
+    /// called once per output callback.
+    pub fn update_rate(&mut self) {
+        if self.buffering {
+            self.rate = 1.0;
+            return;
+        }
+
+        self.fill = self.fill + (self.samples.len() as f64 - self.fill) * FILL_SMOOTHING;
+        let error = (self.fill - self.target as f64) / self.target as f64;
+        self.drift = (self.drift + error * DRIFT_GAIN).max(-MAX_RATE_ADJUSTMENT).min(MAX_RATE_ADJUSTMENT);
+        let adjustment = self.drift + error * MAX_RATE_ADJUSTMENT;
+        self.rate = 1.0 + adjustment.max(-MAX_RATE_ADJUSTMENT).min(MAX_RATE_ADJUSTMENT);
+    }
+
+    /// The next output sample, or `None` while there is nothing to play.
+    pub fn next_sample(&mut self) -> Option<StereoSample> {
+        if self.buffering {
+            if self.samples.len() < self.target {
+                return None;
+            }
+            self.buffering = false;
+            self.fill = self.samples.len() as f64;
+        }
+
+        // Interpolating needs the sample after the current one too.
+        if self.samples.len() < 2 {
+            self.underruns = self.underruns + 1;
+            self.buffering = true;
+            return None;
+        }
+
+        let current = self.samples[0];
+        let next = self.samples[1];
+        let interpolate = |from: i16, to: i16| (from as f64 + (to as f64 - from as f64) * self.phase).round() as i16;
+        let sample = StereoSample {
+            left: interpolate(current.left, next.left),
+            right: interpolate(current.right, next.right)
+        };
+
+        self.phase = self.phase + self.rate;
+        while self.phase >= 1.0 && !self.samples.is_empty() {
+            self.samples.pop_front();
+            self.phase = self.phase - 1.0;
+        }
+
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(value: i16) -> StereoSample {
+        StereoSample {
+            left: value,
+            right: -value
+        }
+    }
+
+    fn filled(extra: usize) -> JitterBuffer {
+        let mut buffer = JitterBuffer::new(Duration::from_millis(10));
+        let length = buffer.target + extra;
+        buffer.push((0..length).map(|index| sample(index as i16)));
+        buffer
+    }
+
+    #[test]
+    fn buffers_up_to_the_target() {
+        let mut buffer = JitterBuffer::new(Duration::from_millis(10));
+        buffer.push((0..(buffer.target - 1)).map(|index| sample(index as i16)));
+        assert!(buffer.next_sample().is_none());
+
+        buffer.push(vec![sample(0)]);
+        let first = buffer.next_sample().unwrap();
+        assert_eq!((first.left, first.right), (0, 0));
+        let second = buffer.next_sample().unwrap();
+        assert_eq!((second.left, second.right), (1, -1));
+    }
+
+    #[test]
+    fn underrun_plays_silence_until_refilled() {
+        let mut buffer = filled(0);
+        let target = buffer.target;
+
+        for _ in 0..(target - 1) {
+            assert!(buffer.next_sample().is_some());
+        }
+        assert!(buffer.next_sample().is_none());
+        assert_eq!(buffer.take_underruns(), 1);
+        assert_eq!(buffer.take_underruns(), 0);
+
+        // Buffering again, a few samples aren't enough to restart.
+        buffer.push((0..10).map(sample));
+        assert!(buffer.next_sample().is_none());
+        assert_eq!(buffer.take_underruns(), 0);
+
+        buffer.push((0..target).map(|index| sample(index as i16)));
+        assert!(buffer.next_sample().is_some());
+    }
+
+    #[test]
+    fn trims_overflow_to_the_target() {
+        let mut buffer = filled(0);
+        let target = buffer.target;
+
+        buffer.push((0..(target * MAX_LATENCY_FACTOR)).map(|index| sample(index as i16)));
+        assert_eq!(buffer.samples.len(), target);
+
+        // The newest samples are the ones kept.
+        let last = buffer.samples.back().unwrap();
+        assert_eq!(last.left, (target * MAX_LATENCY_FACTOR - 1) as i16);
+    }
+
+    #[test]
+    fn rate_follows_fill_level_within_bounds() {
+        let mut buffer = filled(0);
+        buffer.next_sample();
+        buffer.update_rate();
+        assert!((buffer.rate - 1.0).abs() < 1e-6);
+
+        let mut buffer = filled(0);
+        let target = buffer.target;
+        buffer.next_sample();
+        buffer.push((0..(target * 2)).map(|index| sample(index as i16)));
+        for _ in 0..10000 {
+            buffer.update_rate();
+            assert!(buffer.rate > 1.0 && buffer.rate <= 1.0 + MAX_RATE_ADJUSTMENT);
+        }
+        assert!((buffer.rate - (1.0 + MAX_RATE_ADJUSTMENT)).abs() < 1e-9);
+
+        let mut buffer = filled(0);
+        for _ in 0..(target / 2) {
+            buffer.next_sample();
+        }
+        for _ in 0..10000 {
+            buffer.update_rate();
+            assert!(buffer.rate < 1.0 && buffer.rate >= 1.0 - MAX_RATE_ADJUSTMENT);
+        }
+    }
+
+    #[test]
+    fn rate_stays_one_while_buffering() {
+        let mut buffer = JitterBuffer::new(Duration::from_millis(10));
+        buffer.push(vec![sample(0); 4]);
+        buffer.update_rate();
+        assert_eq!(buffer.rate, 1.0);
+    }
+
+    #[test]
+    fn faster_rate_consumes_more_samples() {
+        let mut buffer = filled(1000);
+        buffer.next_sample();
+        buffer.rate = 1.0 + MAX_RATE_ADJUSTMENT;
+        let before = buffer.samples.len();
+
+        for _ in 0..1000 {
+            buffer.next_sample();
+        }
+        let consumed = before - buffer.samples.len();
+        assert!(consumed == 1004 || consumed == 1005, "{}", consumed);
+    }
+}
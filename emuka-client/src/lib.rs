@@ -1,5 +1,7 @@
 mod audio;
+mod jitter;
 mod server;
+pub mod video;
 
 use eyre::Result;
 
@@ -0,0 +1,293 @@
+use std::collections::VecDeque;
+
+use avro_rs::{Reader, types::Value};
+use eyre::{Report, Result};
+
+use emuka_server::{server::api::v1::api::VIDEO_FRAME_API_SCHEMA, video::delta};
+
+/// Decoded frames kept as potential delta bases.
+const FRAMES_CAPACITY: usize = 240;
+
+#[derive(Debug, Clone)]
+pub struct DecodedFrame {
+    pub number: u64,
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>
+}
+
+struct VideoFrameMessage {
+    frame: u64,
+    width: u32,
+    height: u32,
+    format: String,
+    base: i64,
+    palette: Vec<u8>,
+    data: Vec<u8>
+}
+
+fn parse_message(message: &[u8]) -> Result<VideoFrameMessage> {
+    let reader = Reader::with_schema(&VIDEO_FRAME_API_SCHEMA, message)?;
+
+    for record in reader {
+        if let Value::Record(fields) = record? {
+            let mut parsed = VideoFrameMessage {
+                frame: 0,
+                width: 0,
+                height: 0,
+                format: String::new(),
+                base: -1,
+                palette: Vec::new(),
+                data: Vec::new()
+            };
+
+            for (name, value) in fields {
+                match (name.as_str(), value) {
+                    ("frame", Value::Long(frame)) => parsed.frame = frame as u64,
+                    ("width", Value::Int(width)) if width > 0 => parsed.width = width as u32,
+                    ("height", Value::Int(height)) if height > 0 => parsed.height = height as u32,
+                    ("format", Value::String(format)) => parsed.format = format,
+                    ("base", Value::Long(base)) => parsed.base = base,
+                    ("palette", Value::Bytes(palette)) => parsed.palette = palette,
+                    ("data", Value::Bytes(data)) => parsed.data = data,
+                    _ => ()
+                }
+            }
+
+            if parsed.width == 0 || parsed.height == 0 {
+                return Err(Report::msg(format!("Invalid frame size: {}x{}", parsed.width, parsed.height)));
+            }
+
+            return Ok(parsed);
+        }
+    }
+
+    Err(Report::msg("No frame found"))
+}
+
+fn rgba_length(width: u32, height: u32) -> usize {
+    width as usize * height as usize * 4
+}
+
+fn bytes_per_pixel(format: &str) -> Result<usize> {
+    match format {
+        "Rgba" | "Xrgb" => Ok(4),
+        "Indexed" => Ok(1),
+        _ => Err(Report::msg(format!("Unknown format: {}", format)))
+    }
+}
+
+/// Converts pixels of the message's format to RGBA, appending them to `output`.
+fn append_rgba(message: &VideoFrameMessage, pixels: &[u8], output: &mut Vec<u8>) -> Result<()> {
+    match message.format.as_str() {
+        "Rgba" => output.extend_from_slice(pixels),
+        "Xrgb" => {
+            for pixel in pixels.chunks_exact(4) {
+                let word = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                output.extend_from_slice(&(word << 8 | 0xFF).to_be_bytes());
+            }
+        },
+        "Indexed" => {
+            for index in pixels {
+                let start = *index as usize * 4;
+                match message.palette.get(start..(start + 4)) {
+                    Some(color) => output.extend_from_slice(color),
+                    None => return Err(Report::msg(format!("Palette index out of range: {}", index)))
+                }
+            }
+        },
+        format => return Err(Report::msg(format!("Unknown format: {}", format)))
+    }
+
+    Ok(())
+}
+
+/// Rebuilds frames sent by the `/api/v1/screen/stream` WebSocket, keyframes
+/// and delta frames alike. Every decoded frame should be acknowledged with
+/// `ack_message` so the server can use it as the base of the next deltas.
+pub struct VideoDecoder {
+    frames: VecDeque<DecodedFrame>
+}
+
+impl VideoDecoder {
+    pub fn new() -> Self {
+        Self {
+            frames: VecDeque::new()
+        }
+    }
+
+    pub fn ack_message(number: u64) -> String {
+        format!("{{\"ack\": {}}}", number)
+    }
+
+    pub fn decode(&mut self, message: &[u8]) -> Result<&DecodedFrame> {
+        let message = parse_message(message)?;
+
+        let rgba = if message.base < 0 {
+            let mut rgba = Vec::with_capacity(rgba_length(message.width, message.height));
+            append_rgba(&message, &message.data, &mut rgba)?;
+            if rgba.len() != rgba_length(message.width, message.height) {
+                return Err(Report::msg(format!("Keyframe doesn't match its {}x{} size", message.width, message.height)));
+            }
+            rgba
+        } else {
+            let base_number = message.base as u64;
+            // The server never goes back to an older base.
+            while self.frames.front().map_or(false, |frame| frame.number < base_number) {
+                self.frames.pop_front();
+            }
+
+            let base = match self.frames.front() {
+                Some(base) if base.number == base_number => base,
+                _ => return Err(Report::msg(format!("Missing base frame {}", base_number)))
+            };
+
+            if (base.width, base.height) != (message.width, message.height) {
+                return Err(Report::msg(format!(
+                    "Delta frame is {}x{} but its base is {}x{}",
+                    message.width, message.height, base.width, base.height
+                )));
+            }
+
+            let mut rgba = base.rgba.clone();
+            self.apply_tiles(&message, &mut rgba)?;
+            rgba
+        };
+
+        if self.frames.len() >= FRAMES_CAPACITY {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(DecodedFrame {
+            number: message.frame,
+            width: message.width,
+            height: message.height,
+            rgba
+        });
+
+        Ok(self.frames.back().unwrap())
+    }
+
+    fn apply_tiles(&self, message: &VideoFrameMessage, rgba: &mut [u8]) -> Result<()> {
+        if rgba.len() != rgba_length(message.width, message.height) {
+            return Err(Report::msg(format!("Base frame doesn't match the {}x{} size", message.width, message.height)));
+        }
+
+        let bytes_per_pixel = bytes_per_pixel(&message.format)?;
+        let tile_count = delta::tile_count(message.width, message.height);
+        let row_length = message.width as usize * 4;
+        let mut position = 0;
+        let mut converted = Vec::new();
+
+        while position + 2 <= message.data.len() {
+            let tile = u16::from_le_bytes([message.data[position], message.data[position + 1]]);
+            position = position + 2;
+
+            // Also guarantees the tile is at least a pixel wide and high.
+            if tile as u32 >= tile_count {
+                return Err(Report::msg(format!("Tile out of range: {}", tile)));
+            }
+
+            let (x, y, tile_width, tile_height) = delta::tile_bounds(message.width, message.height, tile);
+            let length = (tile_width * tile_height) as usize * bytes_per_pixel;
+            let pixels = message.data.get(position..(position + length))
+                .ok_or(Report::msg("Truncated tile"))?;
+            position = position + length;
+
+            converted.clear();
+            append_rgba(message, pixels, &mut converted)?;
+
+            for (row, tile_row) in converted.chunks_exact(tile_width as usize * 4).enumerate() {
+                let start = (y as usize + row) * row_length + x as usize * 4;
+                rgba[start..(start + tile_row.len())].copy_from_slice(tile_row);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use emuka_server::video::stream::{VideoFormat, VideoFrame, convert};
+
+    use super::*;
+
+    const WIDTH: u32 = 20;
+    const HEIGHT: u32 = 12;
+
+    fn frame(data: Vec<u32>) -> VideoFrame {
+        VideoFrame {
+            number: 0,
+            width: WIDTH,
+            height: HEIGHT,
+            data
+        }
+    }
+
+    fn delta_message(base: &VideoFrame, frame: &VideoFrame, format: VideoFormat) -> VideoFrameMessage {
+        let converted = convert(frame, format);
+        let tiles = delta::changed_tiles(base, frame);
+
+        VideoFrameMessage {
+            frame: 1,
+            width: WIDTH,
+            height: HEIGHT,
+            format: format!("{:?}", converted.format),
+            base: 0,
+            data: delta::extract_tiles(&converted.data, converted.format.bytes_per_pixel(), WIDTH, HEIGHT, &tiles),
+            palette: converted.palette
+        }
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let base = frame((0..(WIDTH * HEIGHT)).map(|pixel| pixel % 3 * 0x404040).collect());
+        let mut data = base.data.clone();
+        data[0] = 0xFF0000;
+        data[(WIDTH + 9) as usize] = 0x00FF00;
+        data[(WIDTH * HEIGHT - 1) as usize] = 0x0000FF;
+        let frame = frame(data);
+
+        for format in [VideoFormat::Rgba, VideoFormat::Xrgb, VideoFormat::Indexed].iter() {
+            let message = delta_message(&base, &frame, *format);
+            let mut rgba = convert(&base, VideoFormat::Rgba).data;
+
+            VideoDecoder::new().apply_tiles(&message, &mut rgba).unwrap();
+            assert_eq!(rgba, convert(&frame, VideoFormat::Rgba).data, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn rejects_tiles_out_of_range() {
+        let base = frame(vec![0; (WIDTH * HEIGHT) as usize]);
+        let mut message = delta_message(&base, &base, VideoFormat::Rgba);
+        message.data = vec![6, 0];
+        let mut rgba = convert(&base, VideoFormat::Rgba).data;
+
+        assert!(VideoDecoder::new().apply_tiles(&message, &mut rgba).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_tiles() {
+        let base = frame(vec![0; (WIDTH * HEIGHT) as usize]);
+        let mut changed = base.data.clone();
+        changed[0] = 1;
+        let mut message = delta_message(&base, &frame(changed), VideoFormat::Rgba);
+        message.data.truncate(message.data.len() - 1);
+        let mut rgba = convert(&base, VideoFormat::Rgba).data;
+
+        assert!(VideoDecoder::new().apply_tiles(&message, &mut rgba).is_err());
+    }
+
+    #[test]
+    fn rejects_size_mismatch() {
+        let base = frame(vec![0; (WIDTH * HEIGHT) as usize]);
+        let mut message = delta_message(&base, &base, VideoFormat::Rgba);
+        message.width = WIDTH * 2;
+        message.data = vec![0, 0];
+        let mut rgba = convert(&base, VideoFormat::Rgba).data;
+
+        assert!(VideoDecoder::new().apply_tiles(&message, &mut rgba).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::{ApuChannel, SAMPLE_RATE, StereoSample};
+
+/// Register offsets from $FF10, as returned by the core.
+const NR10: usize = 0x00;
+const NR30: usize = 0x0A;
+const NR32: usize = 0x0C;
+const NR43: usize = 0x12;
+const NR50: usize = 0x14;
+const NR51: usize = 0x15;
+const NR52: usize = 0x16;
+const WAVE_RAM: usize = 0x20;
+
+/// First register of each channel's NRx1-NRx4 block.
+const CHANNEL_REGISTERS: [usize; 4] = [0x01, 0x06, 0x0B, 0x10];
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// Levels are published once per frame worth of samples.
+const LEVEL_WINDOW: u32 = SAMPLE_RATE / 60;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Envelope {
+    pub initial_volume: u8,
+    pub increasing: bool,
+    /// 0 means the envelope is stopped.
+    pub period: u8
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Sweep {
+    /// 0 means the sweep is stopped.
+    pub period: u8,
+    pub decreasing: bool,
+    pub shift: u8
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Note {
+    /// MIDI note number, 69 being A4.
+    pub midi: u8,
+    pub name: String,
+    /// Off by how many cents from the nearest note.
+    pub cents: i8
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChannelSettings {
+    Pulse {
+        /// 12.5, 25, 50 or 75 percent.
+        duty: f32,
+        envelope: Envelope,
+        sweep: Option<Sweep>
+    },
+    Wave {
+        /// 0, 25, 50 or 100 percent.
+        output_level: u8,
+        /// The 32 4-bit samples of wave RAM, in playback order.
+        samples: Vec<u8>
+    },
+    Noise {
+        envelope: Envelope,
+        clock_shift: u8,
+        divisor_code: u8,
+        /// 7-bit LFSR, which sounds more metallic.
+        short_mode: bool
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ChannelState {
+    pub channel: ApuChannel,
+    /// Whether the channel is currently playing, from NR52.
+    pub enabled: bool,
+    pub dac_enabled: bool,
+    pub left: bool,
+    pub right: bool,
+    pub length_enabled: bool,
+    /// Raw 11-bit period for tone channels.
+    pub period: Option<u16>,
+    pub frequency: f32,
+    pub note: Option<Note>,
+    /// Peak output of the channel over the last frame, from 0 to 1.
+    pub level: f32,
+    pub settings: ChannelSettings
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MasterState {
+    pub enabled: bool,
+    /// 0-7 for each side.
+    pub left_volume: u8,
+    pub right_volume: u8,
+    pub vin_left: bool,
+    pub vin_right: bool
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ApuState {
+    pub master: MasterState,
+    pub channels: Vec<ChannelState>
+}
+
+/// Latest level of each channel, as the bits of an `f32`, so that reading
+/// them never waits on the emulator thread.
+static LEVELS: [AtomicU32; 4] = [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)];
+
+/// Peaks of the current window, owned by the emulator thread.
+#[derive(Default)]
+pub struct LevelMeter {
+    peaks: [i16; 4],
+    samples: u32
+}
+
+impl LevelMeter {
+    /// Feeds the meters with the samples of each channel since the last
+    /// batch, publishing the levels at the end of every window.
+    pub fn write(&mut self, channels: &[Vec<StereoSample>; 4]) {
+        let length = channels.iter().map(|samples| samples.len()).max().unwrap_or(0);
+
+        for position in 0..length {
+            for (index, samples) in channels.iter().enumerate() {
+                if let Some(sample) = samples.get(position) {
+                    let peak = sample.left.saturating_abs().max(sample.right.saturating_abs());
+                    self.peaks[index] = self.peaks[index].max(peak);
+                }
+            }
+
+            self.samples = self.samples + 1;
+            if self.samples >= LEVEL_WINDOW {
+                for (level, peak) in LEVELS.iter().zip(self.peaks.iter()) {
+                    level.store((*peak as f32 / i16::MAX as f32).to_bits(), Ordering::Relaxed);
+                }
+                self.peaks = [0; 4];
+                self.samples = 0;
+            }
+        }
+    }
+}
+
+fn envelope(value: u8) -> Envelope {
+    Envelope {
+        initial_volume: value >> 4,
+        increasing: value & 0x08 != 0,
+        period: value & 0x07
+    }
+}
+
+fn note(frequency: f32) -> Option<Note> {
+    if frequency < 8.0 || frequency > 12600.0 {
+        return None;
+    }
+
+    let semitones = 69.0 + 12.0 * (frequency / 440.0).log2();
+    let midi = semitones.round();
+    let name = NOTE_NAMES[midi as usize % 12];
+    let octave = midi as i32 / 12 - 1;
+
+    Some(Note {
+        midi: midi as u8,
+        name: format!("{}{}", name, octave),
+        cents: ((semitones - midi) * 100.0).round() as i8
+    })
+}
+
+fn noise_frequency(value: u8) -> f32 {
+    let shift = value >> 4;
+    let divisor = match value & 0x07 {
+        0 => 0.5,
+        code => code as f32
+    };
+
+    524288.0 / divisor / (1u32 << (shift + 1)) as f32
+}
+
+impl ApuState {
+    /// Decodes the $FF10-$FF3F register block.
+    pub fn decode(registers: &[u8; 0x30]) -> Self {
+        let status = registers[NR52];
+        let panning = registers[NR51];
+        let volume = registers[NR50];
+
+        let channels = ApuChannel::ALL.iter().map(|channel| {
+            let index = channel.index();
+            let base = CHANNEL_REGISTERS[index];
+            let nrx1 = registers[base];
+            let nrx2 = registers[base + 1];
+            let nrx4 = registers[base + 3];
+            let period = (((nrx4 & 0x07) as u16) << 8) | registers[base + 2] as u16;
+
+            let (settings, dac_enabled, frequency) = match channel {
+                ApuChannel::Pulse1 | ApuChannel::Pulse2 => {
+                    let sweep = match channel {
+                        ApuChannel::Pulse1 => Some(Sweep {
+                            period: (registers[NR10] >> 4) & 0x07,
+                            decreasing: registers[NR10] & 0x08 != 0,
+                            shift: registers[NR10] & 0x07
+                        }),
+                        _ => None
+                    };
+                    let settings = ChannelSettings::Pulse {
+                        duty: [12.5, 25.0, 50.0, 75.0][(nrx1 >> 6) as usize],
+                        envelope: envelope(nrx2),
+                        sweep
+                    };
+                    (settings, nrx2 & 0xF8 != 0, 131072.0 / (2048 - period) as f32)
+                },
+                ApuChannel::Wave => {
+                    let samples = registers[WAVE_RAM..(WAVE_RAM + 0x10)].iter()
+                        .flat_map(|byte| vec![byte >> 4, byte & 0x0F])
+                        .collect();
+                    let settings = ChannelSettings::Wave {
+                        output_level: [0, 100, 50, 25][((registers[NR32] >> 5) & 0x03) as usize],
+                        samples
+                    };
+                    (settings, registers[NR30] & 0x80 != 0, 65536.0 / (2048 - period) as f32)
+                },
+                ApuChannel::Noise => {
+                    let settings = ChannelSettings::Noise {
+                        envelope: envelope(nrx2),
+                        clock_shift: registers[NR43] >> 4,
+                        divisor_code: registers[NR43] & 0x07,
+                        short_mode: registers[NR43] & 0x08 != 0
+                    };
+                    (settings, nrx2 & 0xF8 != 0, noise_frequency(registers[NR43]))
+                }
+            };
+
+            let tone = *channel != ApuChannel::Noise;
+
+            ChannelState {
+                channel: *channel,
+                enabled: status & (1 << index) != 0,
+                dac_enabled,
+                left: panning & (0x10 << index) != 0,
+                right: panning & (1 << index) != 0,
+                length_enabled: nrx4 & 0x40 != 0,
+                period: if tone { Some(period) } else { None },
+                frequency,
+                note: if tone { note(frequency) } else { None },
+                level: f32::from_bits(LEVELS[index].load(Ordering::Relaxed)),
+                settings
+            }
+        }).collect();
+
+        Self {
+            master: MasterState {
+                enabled: status & 0x80 != 0,
+                left_volume: (volume >> 4) & 0x07,
+                right_volume: volume & 0x07,
+                vin_left: volume & 0x80 != 0,
+                vin_right: volume & 0x08 != 0
+            },
+            channels
+        }
+    }
+}
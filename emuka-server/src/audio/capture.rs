@@ -0,0 +1,188 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use eyre::{Report, Result};
+use lazy_static::lazy_static;
+
+use super::{SAMPLE_RATE, StereoSample, wav::WavWriter};
+
+const HEADER_LENGTH: u64 = 44;
+const BYTES_PER_SAMPLE: u64 = 4;
+
+#[derive(Debug, Clone)]
+pub struct CaptureSettings {
+    /// Output path without extension.
+    pub path: PathBuf,
+    /// Start a new file once the current one would grow past this many bytes.
+    pub split_size: Option<u64>,
+    /// Stop once the output stayed silent for this many seconds, after
+    /// having played something.
+    pub until_silence: Option<f32>,
+    /// Samples whose channels are both within this amplitude count as silent.
+    pub silence_threshold: i16
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CaptureStatus {
+    pub capturing: bool,
+    pub files: Vec<PathBuf>,
+    pub samples: u64,
+    pub stopped_by_silence: bool
+}
+
+struct Capture {
+    settings: CaptureSettings,
+    writer: Option<WavWriter>,
+    files: Vec<PathBuf>,
+    /// Samples in every finished file.
+    finished_samples: u64,
+    heard_sound: bool,
+    silent_samples: u64,
+    stopped_by_silence: bool,
+    error: Option<Report>
+}
+
+lazy_static! {
+    /// Only used from the emulator thread, like `stems::STEMS`.
+    static ref CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+}
+
+impl Capture {
+    fn file_path(&self, index: usize) -> PathBuf {
+        if self.settings.split_size.is_some() {
+            let name = self.settings.path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            self.settings.path.with_file_name(format!("{}-{:03}.wav", name, index))
+        } else {
+            self.settings.path.with_extension("wav")
+        }
+    }
+
+    fn open_next(&mut self) -> Result<()> {
+        let path = self.file_path(self.files.len());
+        self.writer = Some(WavWriter::create(&path)?);
+        self.files.push(path);
+        Ok(())
+    }
+
+    /// Finishes the current file, dropping `trim` samples off its end.
+    fn close(&mut self, trim: u64) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            let length = writer.samples().saturating_sub(trim);
+            self.finished_samples = self.finished_samples + writer.finish(Some(length))?;
+        }
+        Ok(())
+    }
+
+    fn samples(&self) -> u64 {
+        self.finished_samples + self.writer.as_ref().map_or(0, |writer| writer.samples())
+    }
+
+    fn status(&self) -> CaptureStatus {
+        CaptureStatus {
+            capturing: self.writer.is_some(),
+            files: self.files.clone(),
+            samples: self.samples(),
+            stopped_by_silence: self.stopped_by_silence
+        }
+    }
+
+    fn write(&mut self, sample: &StereoSample) -> Result<()> {
+        if self.writer.is_none() {
+            return Ok(());
+        }
+
+        if let Some(split_size) = self.settings.split_size {
+            let current_size = HEADER_LENGTH + self.writer.as_ref().unwrap().samples() * BYTES_PER_SAMPLE;
+            if current_size + BYTES_PER_SAMPLE > split_size {
+                self.close(0)?;
+                self.open_next()?;
+            }
+        }
+
+        self.writer.as_mut().unwrap().write(sample)?;
+
+        let threshold = self.settings.silence_threshold;
+        let silent = sample.left.saturating_abs() <= threshold && sample.right.saturating_abs() <= threshold;
+
+        if !silent {
+            self.heard_sound = true;
+            self.silent_samples = 0;
+        } else if self.heard_sound {
+            self.silent_samples = self.silent_samples + 1;
+        }
+
+        if let Some(seconds) = self.settings.until_silence {
+            if self.heard_sound && self.silent_samples >= (seconds.max(0.0) * SAMPLE_RATE as f32) as u64 {
+                self.stopped_by_silence = true;
+                // Trailing silence is trimmed, as far as the current file goes.
+                let trim = self.silent_samples;
+                self.close(trim)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn start(settings: CaptureSettings) -> Result<()> {
+    let mut lock = CAPTURE.lock().unwrap();
+    if lock.as_ref().map_or(false, |capture| capture.writer.is_some()) {
+        return Err(Report::msg("Already capturing"));
+    }
+
+    if settings.split_size.map_or(false, |split_size| split_size <= HEADER_LENGTH + BYTES_PER_SAMPLE) {
+        return Err(Report::msg("Split size is too small"));
+    }
+
+    let mut capture = Capture {
+        settings,
+        writer: None,
+        files: Vec::new(),
+        finished_samples: 0,
+        heard_sound: false,
+        silent_samples: 0,
+        stopped_by_silence: false,
+        error: None
+    };
+    capture.open_next()?;
+
+    *lock = Some(capture);
+    Ok(())
+}
+
+/// Stops the capture, or returns how a capture that already stopped on
+/// silence ended.
+pub fn stop() -> Option<Result<CaptureStatus>> {
+    let mut capture = CAPTURE.lock().unwrap().take()?;
+
+    if let Err(err) = capture.close(0) {
+        return Some(Err(err));
+    }
+
+    match capture.error.take() {
+        Some(err) => Some(Err(err)),
+        None => Some(Ok(capture.status()))
+    }
+}
+
+pub fn status() -> Option<CaptureStatus> {
+    CAPTURE.lock().unwrap().as_ref().map(|capture| capture.status())
+}
+
+pub fn write_samples(samples: &[StereoSample]) {
+    let mut lock = CAPTURE.lock().unwrap();
+    if let Some(capture) = lock.as_mut() {
+        for sample in samples {
+            if capture.error.is_some() {
+                return;
+            }
+
+            if let Err(err) = capture.write(sample) {
+                eprintln!("{}", err);
+                capture.writer = None;
+                capture.error = Some(err);
+            }
+        }
+    }
+}
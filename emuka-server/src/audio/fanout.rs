@@ -0,0 +1,59 @@
+use std::sync::{Mutex, mpsc::{Receiver, Sender, channel}};
+
+use lazy_static::lazy_static;
+
+use super::{AudioSource, MAX_QUEUE_LENGTH, StereoSample, ring::{self, RingConsumer, RingProducer}, stream};
+
+struct Subscriber {
+    source: AudioSource,
+    producer: RingProducer
+}
+
+/// Producer side of every consumer ring. Only the emulator thread uses it,
+/// so its lock is never contended; consumers are handed over through
+/// `SUBSCRIBER_SENDER` instead.
+struct FanOut {
+    subscribers: Vec<Subscriber>,
+    new_subscribers: Receiver<Subscriber>
+}
+
+lazy_static! {
+    static ref FAN_OUT: (Mutex<Sender<Subscriber>>, Mutex<FanOut>) = {
+        let (sender, receiver) = channel();
+        let fan_out = FanOut {
+            subscribers: Vec::new(),
+            new_subscribers: receiver
+        };
+        (Mutex::new(sender), Mutex::new(fan_out))
+    };
+}
+
+/// Creates a ring fed with `source`, starting with the next batch.
+pub fn subscribe(source: AudioSource) -> RingConsumer {
+    let (producer, consumer) = ring::channel(MAX_QUEUE_LENGTH);
+    FAN_OUT.0.lock().unwrap().send(Subscriber { source, producer }).unwrap();
+    consumer
+}
+
+impl FanOut {
+    fn update_subscribers(&mut self) {
+        self.subscribers.extend(self.new_subscribers.try_iter());
+        self.subscribers.retain(|subscriber| !subscriber.producer.is_abandoned());
+    }
+}
+
+/// Called with every batch of mixed samples, along with the per-channel
+/// samples gathered since the previous one.
+pub fn write(mix: &[StereoSample], channels: &[Vec<StereoSample>; 4]) {
+    let mut fan_out = FAN_OUT.1.lock().unwrap();
+    fan_out.update_subscribers();
+
+    for subscriber in &fan_out.subscribers {
+        match subscriber.source {
+            AudioSource::Mix => subscriber.producer.push(mix),
+            AudioSource::Channel(channel) => subscriber.producer.push(&channels[channel.index()])
+        }
+    }
+
+    stream::samples_written();
+}
@@ -0,0 +1,469 @@
+pub mod apu;
+pub mod capture;
+pub mod fanout;
+pub mod resample;
+pub mod ring;
+pub mod stems;
+pub mod stream;
+pub mod vgm;
+pub mod wav;
+
+use core::panic;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::mpsc::*;
+use std::time::{Duration, Instant};
+
+
+use uuid::Uuid;
+
+use self::{resample::Resampler, ring::RingConsumer};
+
+use lazy_static::lazy_static;
+
+use cpal::{OutputCallbackInfo, SampleFormat, SampleRate, Stream, SupportedStreamConfigRange, traits::{DeviceTrait, HostTrait, StreamTrait}};
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+pub struct StereoSample {
+    pub left: i16,
+    pub right: i16
+}
+
+impl StereoSample {
+    pub fn to_byte_array(&self) -> [u8; 4] {
+        let left = self.left.to_le_bytes();
+        let right = self.right.to_le_bytes();
+
+        let mut a = [0u8; 4];
+
+        &a[..2].copy_from_slice(&left);
+        &a[2..].copy_from_slice(&right);
+
+        a
+    }
+
+    pub fn from_byte_array(data: &[u8]) -> Self {
+        assert!(data.len() == 4);
+
+        let mut left_data = [0u8; 2];
+        left_data.copy_from_slice(&data[0..2]);
+        let left: i16 = i16::from_le_bytes(left_data);
+
+        let mut right_data = [0u8; 2];
+        right_data.copy_from_slice(&data[2..4]);
+        let right: i16 = i16::from_le_bytes(right_data);
+
+        return Self {
+            left, right
+        }
+    }
+}
+
+
+
+
+pub struct VecStereoWrapper {
+    pub inner: Option<Vec<StereoSample>>
+}
+
+impl From<Vec<u8>> for VecStereoWrapper {
+    fn from(bytes: Vec<u8>) -> Self {
+        let mut samples: Vec<StereoSample> = vec![];
+        for chunk in bytes.chunks_exact(4) {
+            samples.push(StereoSample::from_byte_array(chunk));
+        }
+
+        Self {
+            inner: Some(samples) 
+        }
+    }
+}
+
+impl Into<Vec<u8>> for VecStereoWrapper {
+    fn into(self) -> Vec<u8> {
+        match self.inner {
+            None => Vec::new(),
+            Some(samples) => {
+                let mut samples_data: Vec<u8> = vec![];
+                
+                for sample in samples {
+                    samples_data.extend_from_slice(&sample.to_byte_array());
+                }
+
+                samples_data
+            }
+        }
+    }
+} 
+
+pub static SAMPLE_RATE: u32 = 48000;
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+pub enum ApuChannel {
+    Pulse1,
+    Pulse2,
+    Wave,
+    Noise
+}
+
+impl ApuChannel {
+    pub const ALL: [ApuChannel; 4] = [Self::Pulse1, Self::Pulse2, Self::Wave, Self::Noise];
+
+    pub fn index(&self) -> usize {
+        match self {
+            Self::Pulse1 => 0,
+            Self::Pulse2 => 1,
+            Self::Wave => 2,
+            Self::Noise => 3
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pulse1 => "pulse1",
+            Self::Pulse2 => "pulse2",
+            Self::Wave => "wave",
+            Self::Noise => "noise"
+        }
+    }
+}
+
+impl FromStr for ApuChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.iter()
+            .find(|channel| channel.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or(format!("Unknown APU channel: {}", s))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleType {
+    I16,
+    F32
+}
+
+/// What a queue's samples are converted to when drained.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    /// 1 for a mono downmix, 2 for interleaved stereo.
+    pub channels: u16,
+    pub sample_type: SampleType
+}
+
+impl Default for AudioFormat {
+    fn default() -> Self {
+        Self {
+            sample_rate: SAMPLE_RATE,
+            channels: 2,
+            sample_type: SampleType::I16
+        }
+    }
+}
+
+impl AudioFormat {
+    pub const MIN_SAMPLE_RATE: u32 = 8000;
+    pub const MAX_SAMPLE_RATE: u32 = 192000;
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sample_rate < Self::MIN_SAMPLE_RATE || self.sample_rate > Self::MAX_SAMPLE_RATE {
+            return Err(format!("Unsupported sample rate: {}", self.sample_rate));
+        }
+        if self.channels != 1 && self.channels != 2 {
+            return Err(format!("Unsupported channel count: {}", self.channels));
+        }
+        Ok(())
+    }
+
+    /// Bytes per sample frame, every channel included.
+    pub fn frame_size(&self) -> usize {
+        let sample_size = match self.sample_type {
+            SampleType::I16 => 2,
+            SampleType::F32 => 4
+        };
+        sample_size * self.channels as usize
+    }
+
+    fn encode(&self, frames: &[[f32; 2]]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(frames.len() * self.frame_size());
+        for frame in frames {
+            let values = if self.channels == 1 {
+                [(frame[0] + frame[1]) / 2.0, 0.0]
+            } else {
+                *frame
+            };
+
+            for value in &values[..self.channels as usize] {
+                match self.sample_type {
+                    SampleType::I16 => {
+                        let value = (value * 32768.0).round().max(i16::MIN as f32).min(i16::MAX as f32) as i16;
+                        data.extend_from_slice(&value.to_le_bytes());
+                    },
+                    SampleType::F32 => data.extend_from_slice(&value.to_le_bytes())
+                }
+            }
+        }
+        data
+    }
+}
+
+/// Past this length, the oldest samples are dropped.
+pub const MAX_QUEUE_LENGTH: usize = (SAMPLE_RATE * 2) as usize;
+/// Queues that were not drained for this long are removed.
+pub const QUEUE_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+const QUEUE_EVICTION_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What a registered queue is fed with.
+#[derive(Debug, Serialize, Copy, Clone, PartialEq)]
+pub enum AudioSource {
+    Mix,
+    Channel(ApuChannel)
+}
+
+#[derive(Debug)]
+pub struct AudioQueue {
+    pub source: AudioSource,
+    samples: RingConsumer,
+    pub last_drain: Instant,
+    pub format: AudioFormat,
+    resampler: Option<Resampler>
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AudioQueueStats {
+    pub id: Uuid,
+    pub source: AudioSource,
+    pub depth: usize,
+    pub capacity: usize,
+    pub dropped: u64,
+    pub position: u64,
+    /// Seconds since the queue was last drained.
+    pub since_last_drain: f32,
+    pub format: AudioFormat
+}
+
+impl AudioQueue {
+    pub fn new(source: AudioSource) -> Self {
+        Self::with_format(source, AudioFormat::default())
+    }
+
+    pub fn with_format(source: AudioSource, format: AudioFormat) -> Self {
+        let resampler = if format.sample_rate != SAMPLE_RATE {
+            Some(Resampler::new(SAMPLE_RATE, format.sample_rate))
+        } else {
+            None
+        };
+
+        Self {
+            source,
+            samples: fanout::subscribe(source),
+            last_drain: Instant::now(),
+            format,
+            resampler
+        }
+    }
+
+    pub fn stats(&self, id: Uuid) -> AudioQueueStats {
+        AudioQueueStats {
+            id,
+            source: self.source,
+            depth: self.samples.len(),
+            capacity: self.samples.capacity(),
+            dropped: self.samples.dropped(),
+            position: self.samples.position(),
+            since_last_drain: self.last_drain.elapsed().as_secs_f32(),
+            format: self.format
+        }
+    }
+
+    /// Takes every queued sample, along with the position of the first one.
+    pub fn drain(&mut self) -> (u64, Vec<StereoSample>) {
+        self.last_drain = Instant::now();
+        self.samples.pop_all()
+    }
+
+    /// Like `drain`, but converted to the queue's format. Also returns how
+    /// many source samples were drained.
+    pub fn drain_encoded(&mut self) -> (u64, usize, Vec<u8>) {
+        let (position, samples) = self.drain();
+        let frames: Vec<[f32; 2]> = samples.iter()
+            .map(|sample| [sample.left as f32 / 32768.0, sample.right as f32 / 32768.0])
+            .collect();
+
+        let frames = match self.resampler.as_mut() {
+            Some(resampler) => resampler.process(&frames),
+            None => frames
+        };
+
+        (position, samples.len(), self.format.encode(&frames))
+    }
+}
+
+pub fn queue_stats() -> Vec<AudioQueueStats> {
+    let lock = SAMPLES_MAP.lock().unwrap();
+    lock.iter().map(|(id, queue)| queue.stats(*id)).collect()
+}
+
+pub fn unregister(id: &Uuid) -> bool {
+    let mut lock = SAMPLES_MAP.lock().unwrap();
+    lock.remove(id).is_some()
+}
+
+/// Removes queues nobody drained for `QUEUE_IDLE_TIMEOUT`.
+fn evict_idle_queues() {
+    let mut lock = SAMPLES_MAP.lock().unwrap();
+    lock.retain(|id, queue| {
+        let keep = queue.last_drain.elapsed() < QUEUE_IDLE_TIMEOUT;
+        if !keep {
+            println!("Evicting idle audio queue {}", id);
+        }
+        keep
+    });
+}
+
+lazy_static! {
+    pub(crate) static ref SAMPLES_MAP: Mutex<HashMap<Uuid, AudioQueue>> = Mutex::new(HashMap::new());
+}
+
+pub enum AudioCommand {
+    Resume,
+    Pause,
+}
+
+pub fn init() -> Sender<AudioCommand> {
+    let (sender, receiver) = channel::<AudioCommand>();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(QUEUE_EVICTION_INTERVAL);
+        loop {
+            interval.tick().await;
+            evict_idle_queues();
+        }
+    });
+
+    tokio::spawn(async move {
+        let stream = init_audio_stream();
+        loop {
+            let command = receiver.recv();
+            
+            match command {
+                Ok(command) => match command {
+                    AudioCommand::Resume => {
+                        stream.play().unwrap();
+                    },
+                    AudioCommand::Pause => {
+                        stream.pause().unwrap();
+                    },
+                },
+                Err(err) => {
+                    println!("{:?}", err);
+                    break;
+                }
+            };
+        }
+        println!("Stream dropped ?");
+    });
+
+
+    sender
+}
+
+
+fn init_audio_stream() -> Stream {
+    let host = cpal::default_host();
+    let device = host.default_output_device().expect("no output device available");
+    println!("{}", device.name().unwrap());
+    let supported_configs_range = device.supported_output_configs()
+    .expect("error while querying configs");
+    let appropriate_configs: Vec<SupportedStreamConfigRange> = supported_configs_range
+    .filter(|conf| conf.channels() == 2)
+    .filter(|conf| conf.max_sample_rate() >= SampleRate(SAMPLE_RATE))
+    .collect();
+
+    println!("number of appropriate configs: {}", appropriate_configs.len());
+
+    if appropriate_configs.is_empty() {
+        panic!("Unsupported on this host device")
+    }
+
+    let perfect_config = appropriate_configs.iter()
+        .find(|conf| conf.sample_format() == SampleFormat::I16);
+
+    let supported_config = match perfect_config {
+        Some(config) => {
+            println!("Perfect config found!");
+            config.clone()
+        },
+        None => appropriate_configs.get(0).unwrap().clone()
+    }.with_sample_rate(SampleRate(SAMPLE_RATE));
+
+    let config = supported_config;
+    println!("{:?}", config);
+    let sample_format = config.sample_format();
+
+    // The output device reads its own ring, outside of the registered
+    // queues, so it never waits on them nor gets evicted.
+    let samples = fanout::subscribe(AudioSource::Mix);
+
+    let stream = match sample_format {
+        cpal::SampleFormat::F32 => run::<f32>( &device, &config.into(), samples),
+        cpal::SampleFormat::I16 => run::<i16>( &device, &config.into(), samples),
+        cpal::SampleFormat::U16 => run::<u16>( &device, &config.into(), samples),
+    };
+
+    stream.play().unwrap();
+
+    return stream;
+}
+
+fn run<T>(device: &cpal::Device, config: &cpal::StreamConfig, mut samples: RingConsumer) -> Stream where
+T: cpal::Sample {
+    let channels = config.channels as usize;
+
+
+    device.build_output_stream(
+        &config, 
+        move |data: &mut [T], info: &OutputCallbackInfo| {
+            write_data(data, info, channels, &mut samples);
+        },
+        |err| println!("{:?}", err)
+    ).unwrap()
+}
+
+
+fn write_data<T>(data: &mut [T], _: &OutputCallbackInfo, channels: usize, samples: &mut RingConsumer) where
+T: cpal::Sample {
+    assert!(channels == 2);
+
+    let (_, available) = samples.pop(data.len() / channels);
+    let silence = StereoSample {left: 0, right: 0};
+
+    for (frame, value) in data.chunks_mut(channels).zip(available.iter().chain(std::iter::repeat(&silence))) {
+        let left: T = cpal::Sample::from::<i16>(&value.left);
+        let right: T = cpal::Sample::from::<i16>(&value.right);
+        let mut frame_iter = frame.iter_mut();
+        *frame_iter.next().unwrap() = left;
+        *frame_iter.next().unwrap() = right;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_array_keeps_channels_apart() {
+        let sample = StereoSample {
+            left: -12345,
+            right: 321
+        };
+        let decoded = StereoSample::from_byte_array(&sample.to_byte_array());
+
+        assert_eq!((decoded.left, decoded.right), (-12345, 321));
+    }
+}
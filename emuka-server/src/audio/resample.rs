@@ -0,0 +1,201 @@
+use std::f64::consts::PI;
+
+/// Zero crossings of the sinc on each side of the kernel, at the output rate
+/// when downsampling.
+const ZERO_CROSSINGS: usize = 16;
+/// Kernel table resolution, between two input samples.
+const PHASES: usize = 256;
+const KAISER_BETA: f64 = 8.0;
+
+/// Zeroth order modified Bessel function, for the Kaiser window.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term = term * (x / (2.0 * k)) * (x / (2.0 * k));
+        sum = sum + term;
+        k = k + 1.0;
+    }
+    sum
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Streaming windowed-sinc resampler for stereo frames. Keeps enough history
+/// between calls that blocks can be fed as they come.
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    /// Input samples per output sample.
+    step: f64,
+    /// Kernel half width, in input samples.
+    half_width: usize,
+    /// One side of the kernel, `PHASES` entries per input sample.
+    kernel: Vec<f32>,
+    history: Vec<[f32; 2]>,
+    /// Position of the next output sample, in input samples from the start
+    /// of `history`.
+    time: f64
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32) -> Self {
+        let step = from as f64 / to as f64;
+        // Lowering the cutoff below the output Nyquist frequency when
+        // downsampling keeps it from aliasing.
+        let cutoff = (1.0 / step).min(1.0);
+        let half_width = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+
+        let length = half_width * PHASES + 1;
+        let normalization = bessel_i0(KAISER_BETA);
+        let kernel = (0..length).map(|index| {
+            let x = index as f64 / PHASES as f64;
+            let ratio = x / half_width as f64;
+            let window = bessel_i0(KAISER_BETA * (1.0 - ratio * ratio).max(0.0).sqrt()) / normalization;
+            (cutoff * sinc(cutoff * x) * window) as f32
+        }).collect();
+
+        Self {
+            step,
+            half_width,
+            kernel,
+            // Starts on silence so the first output sample can be centered
+            // on the first input sample.
+            history: vec![[0.0; 2]; half_width],
+            time: half_width as f64
+        }
+    }
+
+    fn kernel_at(&self, distance: f64) -> f32 {
+        let position = distance.abs() * PHASES as f64;
+        let index = position as usize;
+        if index + 1 >= self.kernel.len() {
+            return 0.0;
+        }
+
+        let fraction = (position - index as f64) as f32;
+        self.kernel[index] + (self.kernel[index + 1] - self.kernel[index]) * fraction
+    }
+
+    pub fn process(&mut self, input: &[[f32; 2]]) -> Vec<[f32; 2]> {
+        self.history.extend_from_slice(input);
+
+        let mut output = Vec::with_capacity((input.len() as f64 / self.step) as usize + 1);
+        while self.time.floor() as usize + self.half_width < self.history.len() {
+            let center = self.time.floor() as usize;
+            let first = center + 1 - self.half_width;
+            let last = center + self.half_width;
+
+            let mut frame = [0.0f32; 2];
+            for index in first..=last {
+                let weight = self.kernel_at(self.time - index as f64);
+                frame[0] = frame[0] + self.history[index][0] * weight;
+                frame[1] = frame[1] + self.history[index][1] * weight;
+            }
+            output.push(frame);
+
+            self.time = self.time + self.step;
+        }
+
+        // Only keep what the next output samples still need.
+        let consumed = (self.time.floor() as usize + 1).saturating_sub(self.half_width);
+        let consumed = consumed.min(self.history.len());
+        self.history.drain(..consumed);
+        self.time = self.time - consumed as f64;
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(length: usize) -> Vec<[f32; 2]> {
+        (0..length).map(|index| {
+            let phase = index as f32 * 0.01;
+            [phase.sin() * 0.5, phase.cos() * 0.25]
+        }).collect()
+    }
+
+    fn assert_close(actual: &[[f32; 2]], expected: &[[f32; 2]], tolerance: f32) {
+        assert_eq!(actual.len(), expected.len());
+        for (index, (actual, expected)) in actual.iter().zip(expected.iter()).enumerate() {
+            for channel in 0..2 {
+                assert!(
+                    (actual[channel] - expected[channel]).abs() <= tolerance,
+                    "sample {} channel {}: {} instead of {}", index, channel, actual[channel], expected[channel]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn same_rate_passes_samples_through() {
+        let input = ramp(1000);
+        let mut resampler = Resampler::new(48000, 48000);
+        let output = resampler.process(&input);
+
+        // Delayed by the kernel half width, which is still in the history.
+        assert_eq!(output.len(), input.len() - resampler.half_width);
+        assert_close(&output, &input[..output.len()], 1e-4);
+    }
+
+    #[test]
+    fn keeps_dc_gain() {
+        for (from, to) in [(48000, 44100), (44100, 48000), (48000, 22050), (22050, 48000)].iter() {
+            let mut resampler = Resampler::new(*from, *to);
+            let output = resampler.process(&vec![[0.5, -0.25]; 4000]);
+
+            // Past the initial silence the kernel starts on.
+            for frame in &output[(output.len() / 2)..] {
+                assert!((frame[0] - 0.5).abs() < 1e-3, "{} to {}: {}", from, to, frame[0]);
+                assert!((frame[1] + 0.25).abs() < 1e-3, "{} to {}: {}", from, to, frame[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn output_length_follows_the_ratio() {
+        for (from, to) in [(48000, 44100), (44100, 48000), (48000, 8000), (32768, 48000)].iter() {
+            let mut resampler = Resampler::new(*from, *to);
+            let half_width = resampler.half_width as f64;
+            let mut input_length = 0;
+            let mut output_length = 0;
+
+            for _ in 0..100 {
+                input_length = input_length + 1024;
+                output_length = output_length + resampler.process(&vec![[0.0; 2]; 1024]).len();
+
+                let expected = (input_length as f64 - half_width) * *to as f64 / *from as f64;
+                assert!((output_length as f64 - expected).abs() <= 1.0, "{} to {}: {} instead of {}", from, to, output_length, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn block_boundaries_dont_matter() {
+        let input = ramp(5000);
+        let expected = Resampler::new(48000, 44100).process(&input);
+
+        let mut resampler = Resampler::new(48000, 44100);
+        let mut output = Vec::new();
+        let mut position = 0;
+        for length in [1, 7, 100, 3, 800, 1, 2000].iter().cycle() {
+            if position == input.len() {
+                break;
+            }
+            let end = (position + length).min(input.len());
+            output.extend(resampler.process(&input[position..end]));
+            position = end;
+        }
+
+        assert_close(&output, &expected, 1e-5);
+    }
+}
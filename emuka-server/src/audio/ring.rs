@@ -0,0 +1,268 @@
+use std::sync::{Arc, atomic::{AtomicU32, AtomicU64, Ordering, fence}};
+
+use super::StereoSample;
+
+fn pack(sample: &StereoSample) -> u32 {
+    ((sample.left as u16 as u32) << 16) | sample.right as u16 as u32
+}
+
+fn unpack(value: u32) -> StereoSample {
+    StereoSample {
+        left: (value >> 16) as u16 as i16,
+        right: value as u16 as i16
+    }
+}
+
+/// Single producer, single consumer ring of samples. The producer never
+/// waits: once the ring is full it overwrites the oldest samples, and the
+/// consumer counts them as dropped.
+#[derive(Debug)]
+struct SampleRing {
+    slots: Box<[AtomicU32]>,
+    /// Samples the producer is about to write, or wrote. Anything older than
+    /// `claimed - capacity` may be overwritten.
+    claimed: AtomicU64,
+    /// Samples the producer finished writing.
+    written: AtomicU64
+}
+
+impl SampleRing {
+    fn capacity(&self) -> u64 {
+        self.slots.len() as u64
+    }
+
+    fn slot(&self, index: u64) -> &AtomicU32 {
+        &self.slots[(index % self.capacity()) as usize]
+    }
+}
+
+/// Creates a ring holding up to `capacity` samples.
+pub fn channel(capacity: usize) -> (RingProducer, RingConsumer) {
+    let ring = Arc::new(SampleRing {
+        slots: (0..capacity.max(1)).map(|_| AtomicU32::new(0)).collect(),
+        claimed: AtomicU64::new(0),
+        written: AtomicU64::new(0)
+    });
+
+    let producer = RingProducer {
+        ring: ring.clone()
+    };
+    let consumer = RingConsumer {
+        ring,
+        read: 0,
+        dropped: 0
+    };
+
+    (producer, consumer)
+}
+
+#[derive(Debug)]
+pub struct RingProducer {
+    ring: Arc<SampleRing>
+}
+
+impl RingProducer {
+    pub fn push(&self, samples: &[StereoSample]) {
+        let ring = &*self.ring;
+        // Only ever written from this side.
+        let start = ring.written.load(Ordering::Relaxed);
+        let end = start + samples.len() as u64;
+
+        ring.claimed.store(end, Ordering::Relaxed);
+        // Orders the claim before the slot writes, for the consumer's check.
+        fence(Ordering::Release);
+
+        for (index, sample) in (start..end).zip(samples.iter()) {
+            ring.slot(index).store(pack(sample), Ordering::Relaxed);
+        }
+
+        ring.written.store(end, Ordering::Release);
+    }
+
+    /// Whether the consumer was dropped, in which case the producer should be too.
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.ring) == 1
+    }
+}
+
+#[derive(Debug)]
+pub struct RingConsumer {
+    ring: Arc<SampleRing>,
+    /// Samples read or dropped so far.
+    read: u64,
+    dropped: u64
+}
+
+impl RingConsumer {
+    /// How many samples were read or dropped so far, which is also the
+    /// position of the next sample.
+    pub fn position(&self) -> u64 {
+        self.read
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.slots.len()
+    }
+
+    /// Samples waiting to be read, overwritten ones included.
+    pub fn len(&self) -> usize {
+        let written = self.ring.written.load(Ordering::Acquire);
+        (written - self.read).min(self.ring.capacity()) as usize
+    }
+
+    /// Reads up to `max` samples, along with the position of the first one.
+    pub fn pop(&mut self, max: usize) -> (u64, Vec<StereoSample>) {
+        let ring = &*self.ring;
+        let written = ring.written.load(Ordering::Acquire);
+
+        let oldest = written.saturating_sub(ring.capacity());
+        if self.read < oldest {
+            self.dropped = self.dropped + (oldest - self.read);
+            self.read = oldest;
+        }
+
+        let end = written.min(self.read.saturating_add(max as u64));
+        let mut samples: Vec<StereoSample> = (self.read..end)
+            .map(|index| unpack(ring.slot(index).load(Ordering::Relaxed)))
+            .collect();
+
+        // Anything the producer started overwriting while we were reading
+        // is stale, and dropped like the rest.
+        fence(Ordering::Acquire);
+        let valid_from = ring.claimed.load(Ordering::Relaxed).saturating_sub(ring.capacity());
+        if valid_from > self.read {
+            let stale = ((valid_from - self.read) as usize).min(samples.len());
+            samples.drain(..stale);
+            self.dropped = self.dropped + stale as u64;
+            self.read = self.read + stale as u64;
+        }
+
+        let position = self.read;
+        self.read = self.read + samples.len() as u64;
+        (position, samples)
+    }
+
+    pub fn pop_all(&mut self) -> (u64, Vec<StereoSample>) {
+        self.pop(usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sample telling its own position, so reads can be checked.
+    fn sample(index: u64) -> StereoSample {
+        StereoSample {
+            left: index as i16,
+            right: !(index as i16)
+        }
+    }
+
+    fn samples(range: std::ops::Range<u64>) -> Vec<StereoSample> {
+        range.map(sample).collect()
+    }
+
+    fn assert_positions(position: u64, samples: &[StereoSample]) {
+        for (offset, read) in samples.iter().enumerate() {
+            let expected = sample(position + offset as u64);
+            assert_eq!((read.left, read.right), (expected.left, expected.right), "sample {}", position + offset as u64);
+        }
+    }
+
+    #[test]
+    fn packs_negative_samples() {
+        let sample = StereoSample {
+            left: -1,
+            right: i16::MIN
+        };
+        let unpacked = unpack(pack(&sample));
+
+        assert_eq!((unpacked.left, unpacked.right), (-1, i16::MIN));
+    }
+
+    #[test]
+    fn reads_in_order() {
+        let (producer, mut consumer) = channel(16);
+        producer.push(&samples(0..10));
+        assert_eq!(consumer.len(), 10);
+
+        let (position, read) = consumer.pop(4);
+        assert_eq!((position, read.len()), (0, 4));
+        assert_positions(position, &read);
+
+        let (position, read) = consumer.pop_all();
+        assert_eq!((position, read.len()), (4, 6));
+        assert_positions(position, &read);
+
+        assert_eq!(consumer.position(), 10);
+        assert_eq!(consumer.dropped(), 0);
+        assert!(consumer.pop_all().1.is_empty());
+    }
+
+    #[test]
+    fn wraps_around() {
+        let (producer, mut consumer) = channel(8);
+
+        for batch in 0..10 {
+            producer.push(&samples((batch * 5)..(batch * 5 + 5)));
+            let (position, read) = consumer.pop_all();
+            assert_eq!((position, read.len()), (batch * 5, 5));
+            assert_positions(position, &read);
+        }
+
+        assert_eq!(consumer.dropped(), 0);
+    }
+
+    #[test]
+    fn overwritten_samples_are_dropped() {
+        let (producer, mut consumer) = channel(8);
+        producer.push(&samples(0..5));
+        producer.push(&samples(5..20));
+        assert_eq!(consumer.len(), 8);
+
+        let (position, read) = consumer.pop_all();
+        assert_eq!((position, read.len()), (12, 8));
+        assert_positions(position, &read);
+        assert_eq!(consumer.dropped(), 12);
+        assert_eq!(consumer.position(), 20);
+    }
+
+    #[test]
+    fn producer_notices_dropped_consumer() {
+        let (producer, consumer) = channel(8);
+        assert!(!producer.is_abandoned());
+
+        drop(consumer);
+        assert!(producer.is_abandoned());
+    }
+
+    #[test]
+    fn concurrent_reads_are_never_torn() {
+        const TOTAL: u64 = 2_000_000;
+        let (producer, mut consumer) = channel(4096);
+
+        let thread = std::thread::spawn(move || {
+            let mut position = 0;
+            while position < TOTAL {
+                let end = (position + 800).min(TOTAL);
+                producer.push(&samples(position..end));
+                position = end;
+            }
+        });
+
+        let mut read_count = 0;
+        while consumer.position() < TOTAL {
+            let (position, read) = consumer.pop(1000);
+            assert_positions(position, &read);
+            read_count = read_count + read.len() as u64;
+        }
+        thread.join().unwrap();
+
+        assert_eq!(read_count + consumer.dropped(), TOTAL);
+    }
+}
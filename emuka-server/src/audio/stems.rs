@@ -0,0 +1,102 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use eyre::{Report, Result};
+use lazy_static::lazy_static;
+
+use super::{ApuChannel, StereoSample, wav::WavWriter};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct StemsSummary {
+    /// The mix first, then one file per APU channel.
+    pub files: Vec<PathBuf>,
+    pub samples: u64
+}
+
+/// The mixed output and each APU channel written to their own WAV file,
+/// sample-aligned with each other.
+struct StemRecording {
+    files: Vec<PathBuf>,
+    mix: WavWriter,
+    channels: Vec<WavWriter>,
+    failed: bool
+}
+
+lazy_static! {
+    /// Only used from the emulator thread: starting and stopping go through
+    /// emulator commands, so the lock is never contended.
+    static ref STEMS: Mutex<Option<StemRecording>> = Mutex::new(None);
+}
+
+/// Starts writing `<path>-mix.wav` and `<path>-<channel>.wav` for every channel.
+pub fn start(path: PathBuf) -> Result<()> {
+    let mut lock = STEMS.lock().unwrap();
+    if lock.is_some() {
+        return Err(Report::msg("Already recording stems"));
+    }
+
+    let name = path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem_path = |stem: &str| path.with_file_name(format!("{}-{}.wav", name, stem));
+
+    let mut files = vec![stem_path("mix")];
+    let mix = WavWriter::create(&files[0])?;
+
+    let mut channels = Vec::with_capacity(ApuChannel::ALL.len());
+    for channel in ApuChannel::ALL.iter() {
+        let channel_path = stem_path(channel.name());
+        channels.push(WavWriter::create(&channel_path)?);
+        files.push(channel_path);
+    }
+
+    *lock = Some(StemRecording {
+        files,
+        mix,
+        channels,
+        failed: false
+    });
+
+    Ok(())
+}
+
+pub fn stop() -> Option<Result<StemsSummary>> {
+    let recording = STEMS.lock().unwrap().take()?;
+
+    // Every file gets the length of the mix, in case a callback was missed.
+    let StemRecording { files, mix, channels, failed } = recording;
+    let samples = mix.samples();
+    let mut result = mix.finish(Some(samples)).map(|_| ());
+    for channel in channels {
+        result = result.and(channel.finish(Some(samples)).map(|_| ()));
+    }
+
+    // The files are still finished so what was written can be played.
+    if failed {
+        result = result.and(Err(Report::msg("Writing stems failed, the files are incomplete")));
+    }
+
+    Some(result.map(|_| StemsSummary {
+        files,
+        samples
+    }))
+}
+
+/// Called with every batch of mixed samples, along with the per-channel
+/// samples gathered since the previous one.
+pub fn write(mix: &[StereoSample], channels: &[Vec<StereoSample>; 4]) {
+    let mut lock = STEMS.lock().unwrap();
+    if let Some(recording) = lock.as_mut() {
+        if recording.failed {
+            return;
+        }
+
+        let result = mix.iter().try_for_each(|sample| recording.mix.write(sample))
+            .and_then(|_| recording.channels.iter_mut().zip(channels.iter())
+                .try_for_each(|(writer, samples)| samples.iter().try_for_each(|sample| writer.write(sample))));
+
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            recording.failed = true;
+        }
+    }
+}
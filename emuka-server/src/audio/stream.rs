@@ -0,0 +1,212 @@
+use std::{convert::Infallible, time::Duration};
+
+use futures::Stream;
+use lazy_static::lazy_static;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use super::SAMPLES_MAP;
+
+/// Streams drain their queue at least this often, so a paused emulator
+/// doesn't get them evicted as idle.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sequence, position, source sample count and data length.
+const BLOCK_HEADER_LENGTH: usize = 24;
+
+lazy_static! {
+    static ref SAMPLES_READY: Notify = Notify::new();
+}
+
+/// Called for every batch of samples fanned out to the queues.
+pub fn samples_written() {
+    SAMPLES_READY.notify_waiters();
+}
+
+/// A run of samples from one queue, in the queue's format.
+#[derive(Debug, Clone)]
+pub struct AudioBlock {
+    /// Incremented by one for every block sent on a stream.
+    pub sequence: u64,
+    /// Index of the first source sample in everything the queue ever
+    /// received. A block not starting where the previous one ended means
+    /// samples were lost.
+    pub position: u64,
+    /// Source samples the block was made of, which differs from the samples
+    /// in `data` when resampling.
+    pub source_samples: u32,
+    pub data: Vec<u8>
+}
+
+impl AudioBlock {
+    /// `[u64 sequence][u64 position][u32 source samples][u32 data length][data]`,
+    /// little endian.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(BLOCK_HEADER_LENGTH + self.data.len());
+        data.extend_from_slice(&self.sequence.to_le_bytes());
+        data.extend_from_slice(&self.position.to_le_bytes());
+        data.extend_from_slice(&self.source_samples.to_le_bytes());
+        data.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        data.extend_from_slice(&self.data);
+        data
+    }
+
+    /// Position right after the last source sample of the block.
+    pub fn end(&self) -> u64 {
+        self.position + self.source_samples as u64
+    }
+}
+
+/// Reassembles blocks out of a byte stream, whatever the chunk boundaries are.
+#[derive(Debug, Default)]
+pub struct AudioBlockReader {
+    buffer: Vec<u8>
+}
+
+impl AudioBlockReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    pub fn next_block(&mut self) -> Option<AudioBlock> {
+        if self.buffer.len() < BLOCK_HEADER_LENGTH {
+            return None;
+        }
+
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&self.buffer[0..8]);
+        let sequence = u64::from_le_bytes(word);
+        word.copy_from_slice(&self.buffer[8..16]);
+        let position = u64::from_le_bytes(word);
+        let mut count = [0u8; 4];
+        count.copy_from_slice(&self.buffer[16..20]);
+        let source_samples = u32::from_le_bytes(count);
+        count.copy_from_slice(&self.buffer[20..24]);
+        let data_length = u32::from_le_bytes(count) as usize;
+
+        let length = BLOCK_HEADER_LENGTH + data_length;
+        if self.buffer.len() < length {
+            return None;
+        }
+
+        let data = self.buffer[BLOCK_HEADER_LENGTH..length].to_vec();
+        self.buffer.drain(..length);
+
+        Some(AudioBlock {
+            sequence,
+            position,
+            source_samples,
+            data
+        })
+    }
+}
+
+/// Takes everything queued for `id`, or `None` once the queue is gone.
+fn drain(id: &Uuid, sequence: u64) -> Option<AudioBlock> {
+    let mut lock = SAMPLES_MAP.lock().unwrap();
+    let queue = lock.get_mut(id)?;
+    let (position, source_samples, data) = queue.drain_encoded();
+
+    Some(AudioBlock {
+        sequence,
+        position,
+        source_samples: source_samples as u32,
+        data
+    })
+}
+
+/// Encoded blocks for the queue `id`, sent as soon as samples are produced.
+/// Ends when the queue is removed, be it unregistered or evicted.
+pub fn blocks(id: Uuid) -> impl Stream<Item = Result<Vec<u8>, Infallible>> {
+    futures::stream::unfold(0u64, move |sequence| async move {
+        loop {
+            // `notify_waiters` only wakes futures that already exist, so this
+            // one is created before draining: a batch written in between
+            // still wakes it up.
+            let notified = SAMPLES_READY.notified();
+
+            let block = drain(&id, sequence)?;
+            if block.source_samples > 0 {
+                return Some((Ok(block.encode()), sequence + 1));
+            }
+
+            let _ = tokio::time::timeout(KEEPALIVE_INTERVAL, notified).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(sequence: u64, length: usize) -> AudioBlock {
+        AudioBlock {
+            sequence,
+            position: sequence * 100,
+            source_samples: length as u32 / 4,
+            data: (0..length).map(|index| (index as u64 + sequence) as u8).collect()
+        }
+    }
+
+    fn assert_same(decoded: &AudioBlock, expected: &AudioBlock) {
+        assert_eq!(decoded.sequence, expected.sequence);
+        assert_eq!(decoded.position, expected.position);
+        assert_eq!(decoded.source_samples, expected.source_samples);
+        assert_eq!(decoded.data, expected.data);
+    }
+
+    #[test]
+    fn encodes_header() {
+        let encoded = block(1, 8).encode();
+
+        assert_eq!(encoded.len(), BLOCK_HEADER_LENGTH + 8);
+        assert_eq!(&encoded[0..8], &1u64.to_le_bytes());
+        assert_eq!(&encoded[8..16], &100u64.to_le_bytes());
+        assert_eq!(&encoded[16..20], &2u32.to_le_bytes());
+        assert_eq!(&encoded[20..24], &8u32.to_le_bytes());
+    }
+
+    #[test]
+    fn reads_blocks_whatever_the_chunk_size() {
+        let blocks: Vec<AudioBlock> = [16, 0, 4, 400, 12].iter().enumerate()
+            .map(|(sequence, length)| block(sequence as u64, *length))
+            .collect();
+        let stream: Vec<u8> = blocks.iter().flat_map(|block| block.encode()).collect();
+
+        for chunk_size in [1, 3, 7, BLOCK_HEADER_LENGTH, 25, 100, stream.len()].iter() {
+            let mut reader = AudioBlockReader::new();
+            let mut decoded = Vec::new();
+
+            for chunk in stream.chunks(*chunk_size) {
+                reader.push(chunk);
+                while let Some(block) = reader.next_block() {
+                    decoded.push(block);
+                }
+            }
+
+            assert_eq!(decoded.len(), blocks.len(), "chunk size {}", chunk_size);
+            for (decoded, expected) in decoded.iter().zip(blocks.iter()) {
+                assert_same(decoded, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn waits_for_complete_blocks() {
+        let encoded = block(3, 40).encode();
+        let mut reader = AudioBlockReader::new();
+
+        reader.push(&encoded[..(BLOCK_HEADER_LENGTH - 1)]);
+        assert!(reader.next_block().is_none());
+        reader.push(&encoded[(BLOCK_HEADER_LENGTH - 1)..(encoded.len() - 1)]);
+        assert!(reader.next_block().is_none());
+        reader.push(&encoded[(encoded.len() - 1)..]);
+
+        assert_same(&reader.next_block().unwrap(), &block(3, 40));
+        assert!(reader.next_block().is_none());
+    }
+}
@@ -0,0 +1,184 @@
+use std::{fs::{self, File}, io::{BufWriter, Seek, SeekFrom, Write}, path::PathBuf, sync::Mutex};
+
+use eyre::{Report, Result};
+use lazy_static::lazy_static;
+
+const VGM_VERSION: u32 = 0x161;
+const VGM_SAMPLE_RATE: u64 = 44100;
+const HEADER_LENGTH: u32 = 0x100;
+const DMG_CLOCK: u64 = 4194304;
+
+const COMMAND_DMG_WRITE: u8 = 0xB3;
+const COMMAND_WAIT: u8 = 0x61;
+const COMMAND_WAIT_NTSC_FRAME: u8 = 0x62;
+const COMMAND_WAIT_PAL_FRAME: u8 = 0x63;
+const COMMAND_WAIT_SHORT: u8 = 0x70;
+const COMMAND_END: u8 = 0x66;
+
+/// APU registers start at $FF10.
+const FIRST_REGISTER: u8 = 0x10;
+const NR52: u8 = 0x26;
+const WAVE_RAM: u8 = 0x30;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct VgmSummary {
+    pub path: PathBuf,
+    pub samples: u64,
+    pub writes: u64
+}
+
+struct VgmLogger {
+    path: PathBuf,
+    file: BufWriter<File>,
+    start_cycles: u64,
+    /// Samples already accounted for by wait commands.
+    samples: u64,
+    writes: u64,
+    failed: bool
+}
+
+lazy_static! {
+    static ref VGM: Mutex<Option<VgmLogger>> = Mutex::new(None);
+}
+
+fn write_header(file: &mut impl Write, length: u32, samples: u64) -> Result<()> {
+    let mut header = [0u8; HEADER_LENGTH as usize];
+    let mut put = |offset: usize, value: u32| header[offset..(offset + 4)].copy_from_slice(&value.to_le_bytes());
+
+    put(0x04, length.saturating_sub(0x04));
+    put(0x08, VGM_VERSION);
+    put(0x18, samples as u32);
+    put(0x34, HEADER_LENGTH - 0x34);
+    put(0x80, DMG_CLOCK as u32);
+    header[0..4].copy_from_slice(b"Vgm ");
+
+    file.write_all(&header)?;
+    Ok(())
+}
+
+impl VgmLogger {
+    fn write_register(&mut self, register: u8, value: u8) -> Result<()> {
+        self.file.write_all(&[COMMAND_DMG_WRITE, register - FIRST_REGISTER, value])?;
+        self.writes = self.writes + 1;
+        Ok(())
+    }
+
+    /// Emits the waits needed to get from the last write to `cycles`.
+    fn wait_until(&mut self, cycles: u64) -> Result<()> {
+        let target = cycles.saturating_sub(self.start_cycles) * VGM_SAMPLE_RATE / DMG_CLOCK;
+        let mut remaining = target.saturating_sub(self.samples);
+        self.samples = self.samples.max(target);
+
+        while remaining > 0 {
+            let wait = match remaining {
+                735 => { self.file.write_all(&[COMMAND_WAIT_NTSC_FRAME])?; 735 },
+                882 => { self.file.write_all(&[COMMAND_WAIT_PAL_FRAME])?; 882 },
+                1..=16 => { self.file.write_all(&[COMMAND_WAIT_SHORT | (remaining as u8 - 1)])?; remaining },
+                _ => {
+                    let wait = remaining.min(u16::MAX as u64);
+                    self.file.write_all(&[COMMAND_WAIT])?;
+                    self.file.write_all(&(wait as u16).to_le_bytes())?;
+                    wait
+                }
+            };
+            remaining = remaining - wait;
+        }
+
+        Ok(())
+    }
+
+    /// Replays the current APU state so playback starts from it.
+    fn write_initial_state(&mut self, registers: &[u8; 0x30]) -> Result<()> {
+        let value = |register: u8| registers[(register - FIRST_REGISTER) as usize];
+
+        // Power the APU on first, other writes are ignored while it is off.
+        self.write_register(NR52, value(NR52) & 0x80)?;
+
+        // Wave RAM is written before the registers that could retrigger channel 3.
+        for register in (WAVE_RAM..(WAVE_RAM + 0x10)).chain(FIRST_REGISTER..NR52) {
+            let mut value = value(register);
+            // Don't retrigger every channel on playback.
+            if register == 0x14 || register == 0x19 || register == 0x1E || register == 0x23 {
+                value = value & 0x7F;
+            }
+            self.write_register(register, value)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self, cycles: u64) -> Result<VgmSummary> {
+        self.wait_until(cycles)?;
+        self.file.write_all(&[COMMAND_END])?;
+
+        let mut file = self.file.into_inner().map_err(|err| err.into_error())?;
+        let length = file.seek(SeekFrom::End(0))? as u32;
+        file.seek(SeekFrom::Start(0))?;
+        write_header(&mut file, length, self.samples)?;
+        file.sync_all()?;
+
+        Ok(VgmSummary {
+            path: self.path,
+            samples: self.samples,
+            writes: self.writes
+        })
+    }
+}
+
+/// Starts logging from the given APU state and cycle count. Should be
+/// called from the emulator thread, between two frames.
+pub fn start(path: PathBuf, registers: &[u8; 0x30], cycles: u64) -> Result<()> {
+    let mut lock = VGM.lock().unwrap();
+    if lock.is_some() {
+        return Err(Report::msg("Already logging VGM"));
+    }
+
+    let path = path.with_extension("vgm");
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = BufWriter::new(File::create(&path)?);
+    write_header(&mut file, 0, 0)?;
+
+    let mut logger = VgmLogger {
+        path,
+        file,
+        start_cycles: cycles,
+        samples: 0,
+        writes: 0,
+        failed: false
+    };
+    logger.write_initial_state(registers)?;
+
+    *lock = Some(logger);
+    Ok(())
+}
+
+pub fn stop(cycles: u64) -> Option<Result<VgmSummary>> {
+    let logger = VGM.lock().unwrap().take()?;
+    let failed = logger.failed;
+    let result = logger.finish(cycles);
+
+    // The file is still finished so what was logged can be played.
+    if failed {
+        return Some(result.and(Err(Report::msg("Writing the VGM log failed, the file is incomplete"))));
+    }
+
+    Some(result)
+}
+
+pub fn write(register: u8, value: u8, cycles: u64) {
+    let mut lock = VGM.lock().unwrap();
+    if let Some(logger) = lock.as_mut() {
+        if logger.failed || register < FIRST_REGISTER || register >= WAVE_RAM + 0x10 {
+            return;
+        }
+
+        let result = logger.wait_until(cycles).and_then(|_| logger.write_register(register, value));
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            logger.failed = true;
+        }
+    }
+}
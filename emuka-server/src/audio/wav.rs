@@ -0,0 +1,86 @@
+use std::{fs::{self, File}, io::{BufWriter, Seek, SeekFrom, Write}, path::Path};
+
+use eyre::Result;
+
+use super::{SAMPLE_RATE, StereoSample};
+
+const HEADER_LENGTH: u64 = 44;
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+
+/// Streams 16-bit stereo PCM at `SAMPLE_RATE` to a WAV file. The header sizes
+/// are only correct once `finish` has been called.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    samples: u64
+}
+
+fn write_header(file: &mut impl Write, samples: u64) -> Result<()> {
+    let data_length = (samples * BLOCK_ALIGN as u64) as u32;
+
+    file.write_all(b"RIFF")?;
+    file.write_all(&(data_length + HEADER_LENGTH as u32 - 8).to_le_bytes())?;
+    file.write_all(b"WAVE")?;
+
+    file.write_all(b"fmt ")?;
+    file.write_all(&16u32.to_le_bytes())?;
+    // PCM
+    file.write_all(&1u16.to_le_bytes())?;
+    file.write_all(&CHANNELS.to_le_bytes())?;
+    file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    file.write_all(&(SAMPLE_RATE * BLOCK_ALIGN as u32).to_le_bytes())?;
+    file.write_all(&BLOCK_ALIGN.to_le_bytes())?;
+    file.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+    file.write_all(b"data")?;
+    file.write_all(&data_length.to_le_bytes())?;
+
+    Ok(())
+}
+
+impl WavWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = BufWriter::new(File::create(path)?);
+        write_header(&mut file, 0)?;
+
+        Ok(Self {
+            file,
+            samples: 0
+        })
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    pub fn write(&mut self, sample: &StereoSample) -> Result<()> {
+        self.file.write_all(&sample.to_byte_array())?;
+        self.samples = self.samples + 1;
+        Ok(())
+    }
+
+    /// Pads with silence or truncates to exactly `length` samples if given,
+    /// then fixes up the header. Returns the final number of samples.
+    pub fn finish(mut self, length: Option<u64>) -> Result<u64> {
+        let silence = StereoSample { left: 0, right: 0 };
+        let length = length.unwrap_or(self.samples);
+
+        while self.samples < length {
+            self.write(&silence)?;
+        }
+
+        let mut file = self.file.into_inner().map_err(|err| err.into_error())?;
+        file.set_len(HEADER_LENGTH + length * BLOCK_ALIGN as u64)?;
+
+        file.seek(SeekFrom::Start(0))?;
+        write_header(&mut file, length)?;
+        file.sync_all()?;
+
+        Ok(length)
+    }
+}
@@ -0,0 +1,282 @@
+use std::{fs, path::PathBuf};
+
+use crate::states::rom_directory;
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+pub enum CheatKind {
+    GameGenie,
+    GameShark
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Cheat {
+    pub code: String,
+    pub kind: CheatKind,
+    pub description: String,
+    pub enabled: bool
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "error")]
+pub enum CheatError {
+    MalformedCode { code: String, reason: String },
+    DuplicateCode { code: String },
+    UnknownCode { code: String },
+    NoGameLoaded,
+    Storage { reason: String }
+}
+
+impl From<std::io::Error> for CheatError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Storage { reason: err.to_string() }
+    }
+}
+
+impl From<serde_json::Error> for CheatError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Storage { reason: err.to_string() }
+    }
+}
+
+fn malformed(code: &str, reason: &str) -> CheatError {
+    CheatError::MalformedCode {
+        code: code.to_owned(),
+        reason: reason.to_owned()
+    }
+}
+
+fn parse_hex(digits: &str) -> u16 {
+    u16::from_str_radix(digits, 16).unwrap()
+}
+
+/// `ABC-DEF`: AB is the new value, FCDE the address with F inverted.
+fn game_genie_address(digits: &str) -> u16 {
+    let digit = |index: usize| parse_hex(&digits[index..(index + 1)]);
+    ((digit(5) ^ 0xF) << 12) | (digit(2) << 8) | (digit(3) << 4) | digit(4)
+}
+
+/// Game Genie codes are `VVA-AAA` or `VVA-AAA-CCC`, and can only patch ROM.
+fn validate_game_genie(code: &str, digits: &str) -> Result<String, CheatError> {
+    if game_genie_address(digits) >= 0x8000 {
+        return Err(malformed(code, "Game Genie codes can only patch ROM ($0000-$7FFF)"));
+    }
+
+    let normalized = if digits.len() == 9 {
+        format!("{}-{}-{}", &digits[0..3], &digits[3..6], &digits[6..9])
+    } else {
+        format!("{}-{}", &digits[0..3], &digits[3..6])
+    };
+
+    Ok(normalized)
+}
+
+/// GameShark codes are `TTVVLLHH`, writing `VV` to RAM at `$HHLL` every frame.
+fn validate_game_shark(code: &str, digits: &str) -> Result<String, CheatError> {
+    let kind = parse_hex(&digits[0..2]);
+    let address = parse_hex(&digits[6..8]) << 8 | parse_hex(&digits[4..6]);
+
+    if !(kind <= 0x01 || (0x80..=0x87).contains(&kind) || (0x90..=0x97).contains(&kind)) {
+        return Err(malformed(code, "Unknown GameShark code type"));
+    }
+
+    if !(0xA000..=0xDFFF).contains(&address) {
+        return Err(malformed(code, "GameShark codes can only patch RAM ($A000-$DFFF)"));
+    }
+
+    Ok(digits.to_owned())
+}
+
+/// Checks `code` and returns its kind along with its normalized form.
+pub fn validate(code: &str) -> Result<(CheatKind, String), CheatError> {
+    let trimmed: String = code.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase();
+
+    let digits: String = trimmed.chars().filter(|c| *c != '-').collect();
+
+    if digits.is_empty() {
+        return Err(malformed(code, "Empty code"));
+    }
+
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(malformed(code, "Codes must only contain hexadecimal digits"));
+    }
+
+    let dashes: Vec<usize> = trimmed.char_indices()
+        .filter(|(_, c)| *c == '-')
+        .map(|(index, _)| index)
+        .collect();
+
+    match digits.len() {
+        6 | 9 => {
+            let expected: &[usize] = if digits.len() == 9 { &[3, 7] } else { &[3] };
+            if !dashes.is_empty() && dashes != expected {
+                return Err(malformed(code, "Misplaced dashes in Game Genie code"));
+            }
+            validate_game_genie(code, &digits).map(|normalized| (CheatKind::GameGenie, normalized))
+        },
+        8 => {
+            if !dashes.is_empty() {
+                return Err(malformed(code, "GameShark codes do not contain dashes"));
+            }
+            validate_game_shark(code, &digits).map(|normalized| (CheatKind::GameShark, normalized))
+        },
+        _ => Err(malformed(code, "Expected a 6 or 9 digit Game Genie code, or an 8 digit GameShark code"))
+    }
+}
+
+/// Cheat list of a single ROM, persisted in its data directory.
+#[derive(Debug)]
+pub struct CheatManager {
+    path: PathBuf,
+    cheats: Vec<Cheat>
+}
+
+impl CheatManager {
+    pub fn load(rom_hash: &str) -> Self {
+        let path = rom_directory(rom_hash).join("cheats.json");
+
+        let cheats = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|err| {
+                eprintln!("{:?}: {}", path, err);
+                Vec::new()
+            }),
+            Err(_) => Vec::new()
+        };
+
+        Self {
+            path,
+            cheats
+        }
+    }
+
+    fn persist(&self) -> Result<(), CheatError> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&self.path, serde_json::to_vec_pretty(&self.cheats)?)?;
+        Ok(())
+    }
+
+    fn find_mut(&mut self, code: &str) -> Result<&mut Cheat, CheatError> {
+        let (_, normalized) = validate(code)?;
+
+        self.cheats.iter_mut()
+            .find(|cheat| cheat.code == normalized)
+            .ok_or(CheatError::UnknownCode { code: normalized })
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn enabled(&self) -> impl Iterator<Item = &Cheat> {
+        self.cheats.iter().filter(|cheat| cheat.enabled)
+    }
+
+    pub fn add(&mut self, code: &str, description: String, enabled: bool) -> Result<Cheat, CheatError> {
+        let (kind, normalized) = validate(code)?;
+
+        if self.cheats.iter().any(|cheat| cheat.code == normalized) {
+            return Err(CheatError::DuplicateCode { code: normalized });
+        }
+
+        let cheat = Cheat {
+            code: normalized,
+            kind,
+            description,
+            enabled
+        };
+
+        self.cheats.push(cheat.clone());
+        self.persist()?;
+        Ok(cheat)
+    }
+
+    pub fn set_enabled(&mut self, code: &str, enabled: bool) -> Result<Cheat, CheatError> {
+        let cheat = self.find_mut(code)?;
+        cheat.enabled = enabled;
+        let cheat = cheat.clone();
+
+        self.persist()?;
+        Ok(cheat)
+    }
+
+    pub fn remove(&mut self, code: &str) -> Result<(), CheatError> {
+        let (_, normalized) = validate(code)?;
+        let count = self.cheats.len();
+
+        self.cheats.retain(|cheat| cheat.code != normalized);
+
+        if self.cheats.len() == count {
+            return Err(CheatError::UnknownCode { code: normalized });
+        }
+
+        self.persist()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(code: &str) -> String {
+        match validate(code) {
+            Err(CheatError::MalformedCode { reason, .. }) => reason,
+            other => panic!("{} should be malformed, got {:?}", code, other)
+        }
+    }
+
+    #[test]
+    fn game_genie_codes() {
+        assert_eq!(game_genie_address("00A17B"), 0x4A17);
+
+        let (kind, normalized) = validate("00A-17B").unwrap();
+        assert_eq!(kind, CheatKind::GameGenie);
+        assert_eq!(normalized, "00A-17B");
+
+        let (kind, normalized) = validate(" 00a17bc49 ").unwrap();
+        assert_eq!(kind, CheatKind::GameGenie);
+        assert_eq!(normalized, "00A-17B-C49");
+    }
+
+    #[test]
+    fn game_genie_outside_rom() {
+        assert_eq!(game_genie_address("00A170"), 0xFA17);
+        assert!(reason("00A-170").contains("ROM"));
+    }
+
+    #[test]
+    fn game_shark_codes() {
+        let (kind, normalized) = validate("01ff04c1").unwrap();
+        assert_eq!(kind, CheatKind::GameShark);
+        assert_eq!(normalized, "01FF04C1");
+
+        assert!(validate("91FF04C1").is_ok());
+        assert!(reason("20FF04C1").contains("type"));
+        assert!(reason("01FF0040").contains("RAM"));
+    }
+
+    #[test]
+    fn bad_separators() {
+        assert!(reason("00A1-7B").contains("dashes"));
+        assert!(reason("00A-17BC-49").contains("dashes"));
+        assert!(reason("01FF-04C1").contains("dashes"));
+    }
+
+    #[test]
+    fn wrong_length() {
+        assert!(reason("").contains("Empty"));
+        assert!(reason("---").contains("Empty"));
+        assert!(reason("00A-17").contains("digit"));
+        assert!(reason("01FF04C").contains("GameShark"));
+    }
+
+    #[test]
+    fn non_hex() {
+        assert!(reason("00G-17B").contains("hexadecimal"));
+        assert!(reason("01FF04CZ").contains("hexadecimal"));
+    }
+}
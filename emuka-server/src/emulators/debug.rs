@@ -0,0 +1,216 @@
+use lazy_static::lazy_static;
+use tokio::sync::broadcast;
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+pub struct DebugAddress {
+    pub bank: Option<u16>,
+    pub address: u16
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Breakpoint {
+    #[serde(flatten)]
+    pub location: DebugAddress,
+    pub condition: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Watchpoint {
+    #[serde(flatten)]
+    pub location: DebugAddress,
+    #[serde(default)]
+    pub read: bool,
+    #[serde(default)]
+    pub write: bool,
+    pub condition: Option<String>
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct DebugPoints {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>
+}
+
+impl DebugPoints {
+    pub fn set_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.retain(|existing| existing.location != breakpoint.location);
+        self.breakpoints.push(breakpoint);
+    }
+
+    pub fn clear_breakpoint(&mut self, location: DebugAddress) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|existing| existing.location != location);
+        self.breakpoints.len() != count
+    }
+
+    pub fn set_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.retain(|existing| existing.location != watchpoint.location);
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn clear_watchpoint(&mut self, location: DebugAddress) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|existing| existing.location != location);
+        self.watchpoints.len() != count
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default)]
+pub struct CpuRegisters {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16
+}
+
+impl CpuRegisters {
+    pub fn to_array(&self) -> [u16; 6] {
+        [self.af, self.bc, self.de, self.hl, self.sp, self.pc]
+    }
+}
+
+#[derive(Debug, Serialize, Copy, Clone)]
+pub struct CpuFlags {
+    pub z: bool,
+    pub n: bool,
+    pub h: bool,
+    pub c: bool
+}
+
+const FLAG_Z: u16 = 0x80;
+const FLAG_N: u16 = 0x40;
+const FLAG_H: u16 = 0x20;
+const FLAG_C: u16 = 0x10;
+
+impl From<u16> for CpuFlags {
+    fn from(af: u16) -> Self {
+        Self {
+            z: af & FLAG_Z != 0,
+            n: af & FLAG_N != 0,
+            h: af & FLAG_H != 0,
+            c: af & FLAG_C != 0
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Copy, Clone)]
+pub struct CpuState {
+    #[serde(flatten)]
+    pub registers: CpuRegisters,
+    pub ime: bool,
+    pub halted: bool,
+    pub stopped: bool,
+    pub flags: CpuFlags
+}
+
+#[derive(Debug, Deserialize, Copy, Clone, Default)]
+pub struct CpuFlagsUpdate {
+    pub z: Option<bool>,
+    pub n: Option<bool>,
+    pub h: Option<bool>,
+    pub c: Option<bool>
+}
+
+/// Partial CPU state; missing fields are left untouched.
+#[derive(Debug, Deserialize, Copy, Clone, Default)]
+pub struct CpuStateUpdate {
+    pub af: Option<u16>,
+    pub bc: Option<u16>,
+    pub de: Option<u16>,
+    #[serde(alias = "hf")]
+    pub hl: Option<u16>,
+    pub sp: Option<u16>,
+    pub pc: Option<u16>,
+    pub ime: Option<bool>,
+    pub halted: Option<bool>,
+    pub stopped: Option<bool>,
+    #[serde(default)]
+    pub flags: CpuFlagsUpdate
+}
+
+fn set_flag(af: u16, flag: u16, value: Option<bool>) -> u16 {
+    match value {
+        Some(true) => af | flag,
+        Some(false) => af & !flag,
+        None => af
+    }
+}
+
+impl CpuState {
+    pub fn new(registers: CpuRegisters, ime: bool, halted: bool, stopped: bool) -> Self {
+        Self {
+            registers,
+            ime,
+            halted,
+            stopped,
+            flags: CpuFlags::from(registers.af)
+        }
+    }
+
+    pub fn apply(&self, update: &CpuStateUpdate) -> Self {
+        let registers = &self.registers;
+
+        let mut af = update.af.unwrap_or(registers.af);
+        af = set_flag(af, FLAG_Z, update.flags.z);
+        af = set_flag(af, FLAG_N, update.flags.n);
+        af = set_flag(af, FLAG_H, update.flags.h);
+        af = set_flag(af, FLAG_C, update.flags.c);
+        // The lower nibble of F is hardwired to 0
+        af = af & 0xFFF0;
+
+        Self::new(
+            CpuRegisters {
+                af,
+                bc: update.bc.unwrap_or(registers.bc),
+                de: update.de.unwrap_or(registers.de),
+                hl: update.hl.unwrap_or(registers.hl),
+                sp: update.sp.unwrap_or(registers.sp),
+                pc: update.pc.unwrap_or(registers.pc)
+            },
+            update.ime.unwrap_or(self.ime),
+            update.halted.unwrap_or(self.halted),
+            update.stopped.unwrap_or(self.stopped)
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Copy, Clone)]
+pub struct StepResult {
+    /// Frames or instructions actually executed.
+    pub steps: u32,
+    /// Whether every requested step ran, or the target PC was reached.
+    pub completed: bool,
+    pub registers: CpuRegisters
+}
+
+#[derive(Debug, Serialize, Copy, Clone)]
+pub enum DebugStopKind {
+    Breakpoint,
+    ReadWatchpoint,
+    WriteWatchpoint
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DebugEvent {
+    pub kind: DebugStopKind,
+    /// The breakpoint's PC, or the address a watchpoint was triggered by.
+    pub address: u16,
+    pub frame: u64,
+    pub registers: CpuRegisters
+}
+
+lazy_static! {
+    pub static ref DEBUG_EVENTS: broadcast::Sender<DebugEvent> = broadcast::channel(64).0;
+}
+
+pub fn emit(event: DebugEvent) {
+    // Nobody listening is not an error.
+    let _ = DEBUG_EVENTS.send(event);
+}
@@ -1,13 +1,19 @@
 pub mod sameboy;
+pub mod rewind;
+pub mod cheats;
+pub mod watch;
+pub mod debug;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
+use eyre::Report;
 use tokio::{sync::mpsc::{UnboundedSender, unbounded_channel}, time};
 use tokio::sync::oneshot::Sender;
+use uuid::Uuid;
 
-use crate::game::{Game, Save};
+use crate::{audio::{apu::ApuState, capture::{CaptureSettings, CaptureStatus}, stems::StemsSummary, vgm::VgmSummary}, game::{Game, Save}, gbs::{GbsFile, GbsSettings, GbsStatus, GbsTrackSelection}, states::SlotMetadata, video::recording::{RecordingSettings, RecordingSummary}};
 
-use self::sameboy::SameBoyEmulator;
+use self::{cheats::{Cheat, CheatError}, debug::{Breakpoint, CpuState, CpuStateUpdate, DebugAddress, DebugPoints, StepResult, Watchpoint}, rewind::{RewindAmount, RewindSettings}, sameboy::SameBoyEmulator, watch::{WatchRequest, WatchUpdate}};
 
 pub trait Emulator {
     fn init(&mut self);
@@ -21,16 +27,58 @@ pub trait Emulator {
 pub enum EmulatorCommand {
     LoadGame(Box<dyn Game>),
     UnloadGame,
+    LoadGbs(Box<GbsFile>, GbsSettings, Sender<Option<GbsStatus>>),
+    SelectGbsTrack(GbsTrackSelection, Sender<Option<GbsStatus>>),
+    GetGbsStatus(Sender<Option<GbsStatus>>),
     LoadSave(Box<dyn Save>),
     RunFrame,
     RunStealth(u32, HashMap<String, u32>, Sender<Option<HashMap<String, u32>>>),
     ReadMemory(String, Sender<Option<String>>),
-    ReadBulkSaveMemory(usize, usize, Sender<Option<Vec<u8>>>),
+    ReadBulkMemory(EmulatorMemoryRegion, usize, usize, Sender<Option<Vec<u8>>>),
+    WriteBulkMemory(EmulatorMemoryRegion, usize, Vec<u8>, Sender<bool>),
     WriteMemory(String, Sender<Option<String>>),
+    Watch(Uuid, WatchRequest, UnboundedSender<WatchUpdate>),
+    Unwatch(Uuid),
+    SetBreakpoint(Breakpoint, Sender<bool>),
+    ClearBreakpoint(DebugAddress, Sender<bool>),
+    SetWatchpoint(Watchpoint, Sender<bool>),
+    ClearWatchpoint(DebugAddress, Sender<bool>),
+    ClearDebugPoints,
+    ListDebugPoints(Sender<DebugPoints>),
+    GetCpuState(Sender<Option<CpuState>>),
+    SetCpuState(CpuStateUpdate, Sender<Option<CpuState>>),
+    StepFrames(u32, Sender<Option<StepResult>>),
+    StepInstructions(u32, Sender<Option<StepResult>>),
+    RunUntil(u16, u32, Sender<Option<StepResult>>),
     Save,
+    SaveState(Sender<Option<Vec<u8>>>),
+    LoadState(Vec<u8>, Sender<bool>),
+    ListStateSlots(Sender<Option<Vec<SlotMetadata>>>),
+    CreateStateSlot(String, bool, Sender<Option<SlotMetadata>>),
+    LoadStateSlot(String, Sender<bool>),
+    RenameStateSlot(String, String, Sender<Option<SlotMetadata>>),
+    DeleteStateSlot(String, Sender<bool>),
+    GetStateSlotThumbnail(String, Sender<Option<ScreenData>>),
+    SetRewind(Option<RewindSettings>),
+    Rewind(RewindAmount, Sender<Option<u64>>),
+    ListCheats(Sender<Result<Vec<Cheat>, CheatError>>),
+    AddCheat(String, String, bool, Sender<Result<Cheat, CheatError>>),
+    SetCheatEnabled(String, bool, Sender<Result<Cheat, CheatError>>),
+    RemoveCheat(String, Sender<Result<(), CheatError>>),
     GetScreenData(Sender<Option<ScreenData>>),
+    StartRecording(RecordingSettings, Sender<bool>),
+    StopRecording(Sender<Option<RecordingSummary>>),
+    StartVgmLog(PathBuf, Sender<bool>),
+    StopVgmLog(Sender<Option<VgmSummary>>),
+    GetApuState(Sender<Option<ApuState>>),
+    StartAudioCapture(CaptureSettings, Sender<bool>),
+    StopAudioCapture(Sender<Option<Result<CaptureStatus, Report>>>),
+    GetAudioCaptureStatus(Sender<Option<CaptureStatus>>),
+    StartStemsRecording(PathBuf, Sender<bool>),
+    StopStemsRecording(Sender<Option<Result<StemsSummary, Report>>>),
     Pause,
     Resume,
+    Reset(EmulatorResetKind),
     Input((EmulatorJoypadInput, bool)),
     Stop,
     Burst(Vec<EmulatorInternalCommand>, Sender<EmulatorInternalCommandResults>)
@@ -66,6 +114,26 @@ pub enum EmulatorJoypadInput {
     RIGHT,
     LEFT
 }
+#[derive(Debug, Deserialize, Copy, Clone)]
+pub enum EmulatorMemoryRegion {
+    SaveRam,
+    Rtc,
+    SystemRam,
+    VideoRam
+}
+
+#[derive(Debug, Deserialize, Copy, Clone)]
+pub enum EmulatorResetKind {
+    Soft,
+    Hard
+}
+
+impl Default for EmulatorResetKind {
+    fn default() -> Self {
+        Self::Soft
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ScreenData {
     pub data: Vec<u8>,
@@ -0,0 +1,261 @@
+use std::collections::VecDeque;
+
+/// Upper bound for `RewindSettings::capacity`, ten minutes of captures at
+/// one per frame.
+pub const MAX_REWIND_CAPACITY: usize = 36000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RewindSettings {
+    /// Frames between two captured states.
+    pub interval: u32,
+    /// Maximum number of states kept.
+    pub capacity: usize
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RewindAmount {
+    Frames(u32),
+    Seconds(f32)
+}
+
+/// Ring buffer of serialized states.
+///
+/// Only the most recent state is kept as-is. Every older state is stored as
+/// the run-length encoded XOR between itself and the state that followed it,
+/// which is mostly zeroes from one capture to the next.
+#[derive(Debug)]
+pub struct RewindBuffer {
+    settings: RewindSettings,
+    current: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>
+}
+
+impl RewindBuffer {
+    pub fn new(settings: RewindSettings) -> Self {
+        let capacity = settings.capacity.max(1).min(MAX_REWIND_CAPACITY);
+
+        Self {
+            settings: RewindSettings {
+                interval: settings.interval.max(1),
+                capacity
+            },
+            current: None,
+            deltas: VecDeque::with_capacity(capacity)
+        }
+    }
+
+    pub fn interval(&self) -> u32 {
+        self.settings.interval
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(current) = self.current.take() {
+            if current.len() == state.len() {
+                self.deltas.push_back(encode_delta(&current, &state));
+            } else {
+                self.deltas.clear();
+            }
+        }
+
+        while self.deltas.len() >= self.settings.capacity {
+            self.deltas.pop_front();
+        }
+
+        self.current = Some(state);
+    }
+
+    /// Steps back `steps` captures from the most recent one, dropping every
+    /// newer capture. Returns how many steps were actually taken along with
+    /// the restored state, or `None` if nothing was captured yet.
+    pub fn rewind(&mut self, steps: usize) -> Option<(usize, Vec<u8>)> {
+        let mut state = self.current.take()?;
+        let mut taken = 0;
+
+        while taken < steps {
+            match self.deltas.pop_back() {
+                Some(delta) => apply_delta(&mut state, &delta),
+                None => break
+            }
+            taken = taken + 1;
+        }
+
+        self.current = Some(state.clone());
+        Some((taken, state))
+    }
+}
+
+fn write_varint(output: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        output.push((value as u8 & 0x7F) | 0x80);
+        value = value >> 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint(input: &[u8], position: &mut usize) -> usize {
+    let mut value = 0usize;
+    let mut shift = 0;
+
+    while let Some(byte) = input.get(*position) {
+        *position = *position + 1;
+        value = value | ((*byte as usize & 0x7F) << shift);
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift = shift + 7;
+    }
+
+    value
+}
+
+/// Encodes `older XOR newer` as a sequence of (zero run, literal length, literals).
+fn encode_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let length = older.len();
+    let mut position = 0;
+
+    while position < length {
+        let zeroes_start = position;
+        while position < length && older[position] == newer[position] {
+            position = position + 1;
+        }
+
+        if position == length {
+            break;
+        }
+
+        let literal_start = position;
+        // A single identical byte is cheaper to keep inside the literal than to split on.
+        while position < length
+            && (older[position] != newer[position]
+                || (position + 1 < length && older[position + 1] != newer[position + 1])) {
+            position = position + 1;
+        }
+
+        write_varint(&mut output, literal_start - zeroes_start);
+        write_varint(&mut output, position - literal_start);
+        output.extend(
+            older[literal_start..position].iter()
+                .zip(newer[literal_start..position].iter())
+                .map(|(a, b)| a ^ b)
+        );
+    }
+
+    output
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut input_position = 0;
+    let mut state_position = 0;
+
+    while input_position < delta.len() {
+        state_position = state_position + read_varint(delta, &mut input_position);
+        let literal_length = read_varint(delta, &mut input_position);
+
+        let literals = &delta[input_position..(input_position + literal_length)];
+        for (byte, literal) in state[state_position..(state_position + literal_length)].iter_mut().zip(literals) {
+            *byte = *byte ^ literal;
+        }
+
+        input_position = input_position + literal_length;
+        state_position = state_position + literal_length;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(capacity: usize) -> RewindSettings {
+        RewindSettings {
+            interval: 1,
+            capacity
+        }
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let older: Vec<u8> = (0..=255).collect();
+        let mut newer = older.clone();
+        newer[0] = 0xFF;
+        newer[10] = 0;
+        newer[12] = 0;
+        newer[255] = 1;
+
+        let delta = encode_delta(&older, &newer);
+        let mut state = newer.clone();
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, older);
+    }
+
+    #[test]
+    fn identical_states_have_empty_delta() {
+        let state = vec![0x42; 1024];
+        assert!(encode_delta(&state, &state).is_empty());
+    }
+
+    #[test]
+    fn long_runs_use_multi_byte_varints() {
+        let older = vec![0; 1000];
+        let mut newer = older.clone();
+        newer[900] = 1;
+
+        let delta = encode_delta(&older, &newer);
+        assert_eq!(delta, vec![0x84, 0x07, 1, 1]);
+
+        let mut state = newer.clone();
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, older);
+    }
+
+    #[test]
+    fn rewinds_through_captures() {
+        let mut buffer = RewindBuffer::new(settings(10));
+        for value in 0..5u8 {
+            buffer.push(vec![value; 16]);
+        }
+
+        assert_eq!(buffer.rewind(2), Some((2, vec![2; 16])));
+        assert_eq!(buffer.rewind(0), Some((0, vec![2; 16])));
+        assert_eq!(buffer.rewind(10), Some((2, vec![0; 16])));
+    }
+
+    #[test]
+    fn drops_oldest_captures_past_capacity() {
+        let mut buffer = RewindBuffer::new(settings(3));
+        for value in 0..10u8 {
+            buffer.push(vec![value; 4]);
+        }
+
+        assert_eq!(buffer.rewind(100), Some((2, vec![7; 4])));
+    }
+
+    #[test]
+    fn size_change_drops_older_captures() {
+        let mut buffer = RewindBuffer::new(settings(10));
+        buffer.push(vec![1; 4]);
+        buffer.push(vec![2; 8]);
+
+        assert_eq!(buffer.rewind(1), Some((0, vec![2; 8])));
+    }
+
+    #[test]
+    fn cleared_buffer_has_nothing_to_rewind() {
+        let mut buffer = RewindBuffer::new(settings(10));
+        buffer.push(vec![1; 4]);
+        buffer.clear();
+
+        assert_eq!(buffer.rewind(1), None);
+    }
+
+    #[test]
+    fn capacity_is_clamped() {
+        let buffer = RewindBuffer::new(settings(usize::MAX));
+        assert_eq!(buffer.settings.capacity, MAX_REWIND_CAPACITY);
+    }
+}
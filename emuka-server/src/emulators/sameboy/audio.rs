@@ -1,12 +1,57 @@
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
 use crate::audio::StereoSample;
-use crate::audio::SAMPLES_MAP;
+use crate::audio::{apu::LevelMeter, capture, fanout, stems, vgm};
+use crate::video::recording;
+
+/// Samples of the frame being emulated. Only the core callbacks use it, and
+/// they all run on the emulator thread, so its lock is never contended and
+/// its buffers are reused from one batch to the next.
+#[derive(Default)]
+struct AudioFrame {
+    mix: Vec<StereoSample>,
+    channels: [Vec<StereoSample>; 4],
+    levels: LevelMeter
+}
+
+lazy_static! {
+    static ref FRAME: Mutex<AudioFrame> = Mutex::new(AudioFrame::default());
+}
+
+/// Receives the interleaved stereo samples of a whole frame at once, and
+/// flushes the channel samples gathered since the previous batch.
+pub fn audio_sample_batch(data: &[i16]) -> usize {
+    let mut frame = FRAME.lock().unwrap();
+    let frame = &mut *frame;
 
-pub fn audio_sample(left: i16, right: i16) {
-    let mut lock = SAMPLES_MAP.lock().unwrap();
-    let map = &mut *lock;
+    frame.mix.clear();
+    frame.mix.extend(data.chunks_exact(2)
+        .map(|sample| StereoSample {left: sample[0], right: sample[1]}));
 
-    for (_, queue) in map.iter_mut() {
-        queue.push_back (StereoSample {left, right});
+    recording::write_samples(&frame.mix);
+    capture::write_samples(&frame.mix);
+    stems::write(&frame.mix, &frame.channels);
+    frame.levels.write(&frame.channels);
+    fanout::write(&frame.mix, &frame.channels);
+
+    for channel in frame.channels.iter_mut() {
+        channel.clear();
+    }
+
+    frame.mix.len()
+}
+
+/// Receives the left and right output of each APU channel, in channel order,
+/// as they are produced. They are written out with the next mixed batch.
+pub fn channel_samples(samples: &[i16; 8]) {
+    let mut frame = FRAME.lock().unwrap();
+    for (index, channel) in frame.channels.iter_mut().enumerate() {
+        channel.push(StereoSample {left: samples[index * 2], right: samples[index * 2 + 1]});
     }
 }
 
+pub fn apu_write(address: u8, value: u8, cycles: u64) {
+    vgm::write(address, value, cycles);
+}
@@ -3994,6 +3994,73 @@ extern "C" {
 extern "C" {
     pub fn emuka_run_stealth(jump_location: u16, registers: *mut u16);
 }
+extern "C" {
+    pub fn emuka_set_breakpoint(
+        bank: u16,
+        address: u16,
+        condition: *const ::std::os::raw::c_char,
+    ) -> bool;
+}
+extern "C" {
+    pub fn emuka_clear_breakpoint(bank: u16, address: u16) -> bool;
+}
+extern "C" {
+    pub fn emuka_set_watchpoint(
+        bank: u16,
+        address: u16,
+        flags: u8,
+        condition: *const ::std::os::raw::c_char,
+    ) -> bool;
+}
+extern "C" {
+    pub fn emuka_clear_watchpoint(bank: u16, address: u16) -> bool;
+}
+extern "C" {
+    pub fn emuka_clear_debug_points();
+}
+extern "C" {
+    pub fn emuka_debug_stopped(kind: *mut u8, address: *mut u16, registers: *mut u16) -> bool;
+}
+extern "C" {
+    pub fn emuka_get_registers(registers: *mut u16);
+}
+extern "C" {
+    pub fn emuka_step_instructions(count: u32, registers: *mut u16) -> u32;
+}
+extern "C" {
+    pub fn emuka_run_until(
+        pc: u16,
+        max_instructions: u32,
+        executed: *mut u32,
+        registers: *mut u16,
+    ) -> bool;
+}
+extern "C" {
+    pub fn emuka_get_cpu_state(
+        registers: *mut u16,
+        ime: *mut bool,
+        halted: *mut bool,
+        stopped: *mut bool,
+    );
+}
+extern "C" {
+    pub fn emuka_set_cpu_state(registers: *const u16, ime: bool, halted: bool, stopped: bool);
+}
+pub type emuka_channel_sample_t = ::std::option::Option<unsafe extern "C" fn(samples: *const i16)>;
+extern "C" {
+    pub fn emuka_set_channel_sample_callback(callback: emuka_channel_sample_t);
+}
+pub type emuka_apu_write_t =
+    ::std::option::Option<unsafe extern "C" fn(address: u8, value: u8, cycles: u64)>;
+extern "C" {
+    pub fn emuka_set_apu_write_callback(callback: emuka_apu_write_t);
+}
+extern "C" {
+    pub fn emuka_get_apu_registers(registers: *mut u8);
+}
+extern "C" {
+    pub fn emuka_get_cycle_count() -> u64;
+}
 pub type __builtin_va_list = *mut ::std::os::raw::c_char;
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
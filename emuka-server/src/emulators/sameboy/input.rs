@@ -19,3 +19,7 @@ pub fn store_input(input: SameboyJoypadInput, pressed: bool) {
         INPUT_STATE.fetch_and(!input_value as i16, Ordering::Relaxed);
     }
 }
+
+pub fn clear_input() {
+    INPUT_STATE.store(0, Ordering::Release);
+}
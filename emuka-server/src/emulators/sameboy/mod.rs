@@ -1,12 +1,14 @@
-use std::{collections::HashMap, time::Instant};
+use std::{collections::HashMap, path::PathBuf, time::Instant};
+use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
 use eyre::Result;
 use tokio::sync::oneshot::Sender;
 use lazy_static::lazy_static;
 use onig::Regex;
 
-use crate::game::{self, Game};
+use crate::{audio::{apu::ApuState, capture::{self, CaptureSettings}, stems, vgm::{self, VgmSummary}}, game::{self, Game}, gbs::{GbsFile, GbsGame, GbsPlayback, GbsSettings, GbsStatus, GbsTrackSelection}, states::{SlotManager, SlotMetadata}, video::recording::{self, RecordingSettings, RecordingSummary}};
 
-use super::{EmulatorCommand, EmulatorInternalCommand, EmulatorInternalCommandResult, EmulatorInternalCommandResults};
+use super::{EmulatorCommand, EmulatorMemoryRegion, EmulatorResetKind, ScreenData, cheats::{Cheat, CheatError, CheatManager}, debug::{self, Breakpoint, CpuState, CpuStateUpdate, DebugAddress, DebugEvent, DebugPoints, StepResult, Watchpoint}, rewind::{RewindAmount, RewindBuffer, RewindSettings}, watch::{WatchRequest, WatchSubscription, WatchUpdate}, EmulatorInternalCommand, EmulatorInternalCommandResult, EmulatorInternalCommandResults};
 
 #[allow(warnings)]
 mod bindings;
@@ -23,7 +25,14 @@ lazy_static! {
 pub struct SameBoyEmulator {
     game_path: Option<String>,
     save_path: Option<String>,
+    slots: Option<SlotManager>,
+    cheats: Option<CheatManager>,
     running: bool,
+    emulated_frames: u64,
+    rewind: Option<RewindBuffer>,
+    watches: HashMap<Uuid, WatchSubscription>,
+    debug_points: DebugPoints,
+    gbs: Option<GbsPlayback>,
 
     before: Option<std::time::Instant>,
     frames: usize,
@@ -37,7 +46,14 @@ impl Default for SameBoyEmulator {
         Self {
             game_path: None,
             save_path: None,
+            slots: None,
+            cheats: None,
             running: false,
+            emulated_frames: 0,
+            rewind: None,
+            watches: HashMap::new(),
+            debug_points: DebugPoints::default(),
+            gbs: None,
             before: None,
             frames: 0,
             frame_interval: 0,
@@ -58,12 +74,29 @@ impl SameBoyEmulator {
         };
 
         wrapper::load_game(&game_info);
+        let slots = SlotManager::new(game.as_ref());
+        self.cheats = Some(CheatManager::load(slots.rom_hash()));
+        self.slots = Some(slots);
+        self.apply_cheats();
+        self.apply_debug_points();
+        self.emulated_frames = 0;
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
         println!("Game loaded");
     }
 
     fn unload_game(&mut self) {
         self.running = false;
         self.game_path = None;
+        self.gbs = None;
+        self.slots = None;
+        self.cheats = None;
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+        self.stop_recording();
+        self.stop_vgm_log();
         wrapper::unload_game();
     }
 
@@ -90,7 +123,8 @@ impl SameBoyEmulator {
         }
 
         if self.running && !self.skip_next {
-            wrapper::run_frame();
+            self.emulate_frame();
+            self.advance_gbs_track();
         }
 
         self.skip_next = false;
@@ -130,12 +164,261 @@ impl SameBoyEmulator {
         }
     }
 
+    fn reset(&mut self, kind: EmulatorResetKind) {
+        if self.game_path.is_none() {
+            return;
+        }
+
+        wrapper::reset();
+        input::clear_input();
+
+        if let EmulatorResetKind::Hard = kind {
+            if let Some(save_path) = self.save_path.as_ref() {
+                wrapper::load_save(save_path);
+            }
+        }
+
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+
+        self.emulated_frames = 0;
+        self.before = None;
+        self.frames = 0;
+        self.delta = 0;
+        self.skip_next = false;
+        println!("Game reset ({:?})", kind);
+    }
+
+    /// Runs a single frame, and returns whether it was interrupted by a debug point.
+    fn emulate_frame(&mut self) -> bool {
+        wrapper::run_frame();
+        self.emulated_frames = self.emulated_frames + 1;
+
+        let stop = wrapper::debug_stopped();
+        if let Some((kind, address, registers)) = stop {
+            self.running = false;
+            println!("Stopped at {:?} ${:04X}", kind, registers.pc);
+            debug::emit(DebugEvent {
+                kind,
+                address,
+                frame: self.emulated_frames,
+                registers
+            });
+        }
+
+        if let Some(rewind) = self.rewind.as_mut() {
+            if self.emulated_frames % rewind.interval() as u64 == 0 {
+                match wrapper::serialize() {
+                    Ok(state) => rewind.push(state),
+                    Err(err) => eprintln!("{}", err)
+                }
+            }
+        }
+
+        let frame = self.emulated_frames;
+        self.watches.retain(|_, subscription| subscription.update(frame, evaluate_read));
+
+        stop.is_some()
+    }
+
+    fn set_rewind(&mut self, settings: Option<RewindSettings>) {
+        self.rewind = settings.map(RewindBuffer::new);
+    }
+
+    fn rewind(&mut self, amount: RewindAmount) -> Option<u64> {
+        if self.game_path.is_none() {
+            return None;
+        }
+
+        let rewind = self.rewind.as_mut()?;
+        let interval = rewind.interval() as u64;
+
+        let frames = match amount {
+            RewindAmount::Frames(frames) => frames as u64,
+            RewindAmount::Seconds(seconds) => (seconds.max(0.0) * FRAME_RATE).round() as u64
+        };
+
+        // The latest capture is already up to `interval - 1` frames behind.
+        let since_capture = self.emulated_frames % interval;
+        let steps = (frames.saturating_sub(since_capture) + interval - 1) / interval;
+
+        let (taken, state) = rewind.rewind(steps as usize)?;
+
+        if let Err(err) = wrapper::unserialize(&state) {
+            eprintln!("{}", err);
+            return None;
+        }
+
+        let rewound = since_capture + taken as u64 * interval;
+        self.emulated_frames = self.emulated_frames.saturating_sub(rewound);
+        Some(rewound)
+    }
+
     fn save(&self) {
         if let Some(save_path) = self.save_path.as_ref() {
             wrapper::save(&save_path);
         }
     }
 
+    fn save_state(&mut self) -> Option<Vec<u8>> {
+        if self.game_path.is_none() {
+            return None;
+        }
+
+        match wrapper::serialize() {
+            Ok(state) => Some(state),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        }
+    }
+
+    fn load_state(&mut self, state: Vec<u8>) -> bool {
+        if self.game_path.is_none() {
+            return false;
+        }
+
+        match wrapper::unserialize(&state) {
+            Ok(_) => {
+                // Captures from before the load would rewind into another timeline.
+                if let Some(rewind) = self.rewind.as_mut() {
+                    rewind.clear();
+                }
+                true
+            },
+            Err(err) => {
+                eprintln!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn list_state_slots(&mut self) -> Option<Vec<SlotMetadata>> {
+        let slots = self.slots.as_ref()?;
+
+        match slots.list() {
+            Ok(list) => Some(list),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        }
+    }
+
+    fn create_state_slot(&mut self, name: String, with_thumbnail: bool) -> Option<SlotMetadata> {
+        let state = self.save_state()?;
+        let thumbnail = if with_thumbnail { wrapper::get_screen_data() } else { None };
+        let slots = self.slots.as_ref()?;
+
+        match slots.create(&name, &state, self.emulated_frames, thumbnail.as_ref()) {
+            Ok(metadata) => Some(metadata),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        }
+    }
+
+    fn load_state_slot(&mut self, name: String) -> bool {
+        let loaded = match self.slots.as_ref() {
+            Some(slots) => slots.load(&name),
+            None => return false
+        };
+
+        match loaded {
+            Ok((metadata, state)) => {
+                if !self.load_state(state) {
+                    return false;
+                }
+                self.emulated_frames = metadata.frame_count;
+                true
+            },
+            Err(err) => {
+                eprintln!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn rename_state_slot(&mut self, from: String, to: String) -> Option<SlotMetadata> {
+        let slots = self.slots.as_ref()?;
+
+        match slots.rename(&from, &to) {
+            Ok(metadata) => Some(metadata),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        }
+    }
+
+    fn delete_state_slot(&mut self, name: String) -> bool {
+        let slots = match self.slots.as_ref() {
+            Some(slots) => slots,
+            None => return false
+        };
+
+        match slots.delete(&name) {
+            Ok(_) => true,
+            Err(err) => {
+                eprintln!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn get_state_slot_thumbnail(&mut self, name: String) -> Option<ScreenData> {
+        let slots = self.slots.as_ref()?;
+
+        match slots.thumbnail(&name) {
+            Ok(thumbnail) => thumbnail,
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        }
+    }
+
+    fn apply_cheats(&self) {
+        wrapper::cheat_reset();
+
+        if let Some(cheats) = self.cheats.as_ref() {
+            for (index, cheat) in cheats.enabled().enumerate() {
+                if let Err(err) = wrapper::cheat_set(index as u32, true, &cheat.code) {
+                    eprintln!("{}", err);
+                }
+            }
+        }
+    }
+
+    fn list_cheats(&mut self) -> Result<Vec<Cheat>, CheatError> {
+        let cheats = self.cheats.as_ref().ok_or(CheatError::NoGameLoaded)?;
+        Ok(cheats.list().to_vec())
+    }
+
+    fn add_cheat(&mut self, code: String, description: String, enabled: bool) -> Result<Cheat, CheatError> {
+        let cheats = self.cheats.as_mut().ok_or(CheatError::NoGameLoaded)?;
+        let cheat = cheats.add(&code, description, enabled)?;
+        self.apply_cheats();
+        Ok(cheat)
+    }
+
+    fn set_cheat_enabled(&mut self, code: String, enabled: bool) -> Result<Cheat, CheatError> {
+        let cheats = self.cheats.as_mut().ok_or(CheatError::NoGameLoaded)?;
+        let cheat = cheats.set_enabled(&code, enabled)?;
+        self.apply_cheats();
+        Ok(cheat)
+    }
+
+    fn remove_cheat(&mut self, code: String) -> Result<(), CheatError> {
+        let cheats = self.cheats.as_mut().ok_or(CheatError::NoGameLoaded)?;
+        cheats.remove(&code)?;
+        self.apply_cheats();
+        Ok(())
+    }
+
     fn run_stealth(&mut self, jump_location: u32, mut state: HashMap<String, u32>) -> Result<HashMap<String, u32>> {
         if !self.running {
             return Err(eyre::Report::msg("Game is not running!"));
@@ -155,28 +438,317 @@ impl SameBoyEmulator {
     }
 
     fn read_memory(&mut self, request: String) -> Option<String> {
-        if RE_IS_ASSIGNMENT.find(&request).is_some() {
+        evaluate_read(&request)
+    }
+
+    fn read_memory_and_send(&mut self, request: String, sender: Sender<Option<String>>) {
+        sender.send(self.read_memory(request)).unwrap()
+    }
+
+    fn read_bulk_memory(&mut self, region: EmulatorMemoryRegion, offset: usize, length: usize) -> Option<Vec<u8>> {
+        match wrapper::get_memory(region, offset, length) {
+            Ok(data) => Some(data),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        }
+    }
+
+    fn read_bulk_memory_and_send(&mut self, region: EmulatorMemoryRegion, offset: usize, length: usize, sender: Sender<Option<Vec<u8>>>) {
+        if self.game_path.is_none() {
+            sender.send(None).unwrap();
+            return;
+        }
+
+        sender.send(self.read_bulk_memory(region, offset, length)).unwrap()
+    }
+
+    fn write_bulk_memory(&mut self, region: EmulatorMemoryRegion, offset: usize, data: Vec<u8>) -> bool {
+        if self.game_path.is_none() {
+            return false;
+        }
+
+        match wrapper::set_memory(region, offset, &data) {
+            Ok(_) => true,
+            Err(err) => {
+                eprintln!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn watch(&mut self, id: Uuid, request: WatchRequest, sender: UnboundedSender<WatchUpdate>) {
+        self.watches.insert(id, WatchSubscription::new(request, sender));
+    }
+
+    fn apply_debug_points(&self) {
+        wrapper::clear_debug_points();
+
+        for breakpoint in self.debug_points.breakpoints.iter() {
+            if let Err(err) = wrapper::set_breakpoint(&breakpoint.location, breakpoint.condition.as_deref()) {
+                eprintln!("{}", err);
+            }
+        }
+
+        for watchpoint in self.debug_points.watchpoints.iter() {
+            if let Err(err) = wrapper::set_watchpoint(&watchpoint.location, watchpoint_flags(watchpoint), watchpoint.condition.as_deref()) {
+                eprintln!("{}", err);
+            }
+        }
+    }
+
+    fn set_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        match wrapper::set_breakpoint(&breakpoint.location, breakpoint.condition.as_deref()) {
+            Ok(_) => {
+                self.debug_points.set_breakpoint(breakpoint);
+                true
+            },
+            Err(err) => {
+                eprintln!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn clear_breakpoint(&mut self, location: DebugAddress) -> bool {
+        wrapper::clear_breakpoint(&location);
+        self.debug_points.clear_breakpoint(location)
+    }
+
+    fn set_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        if !watchpoint.read && !watchpoint.write {
+            return false;
+        }
+
+        match wrapper::set_watchpoint(&watchpoint.location, watchpoint_flags(&watchpoint), watchpoint.condition.as_deref()) {
+            Ok(_) => {
+                self.debug_points.set_watchpoint(watchpoint);
+                true
+            },
+            Err(err) => {
+                eprintln!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn clear_watchpoint(&mut self, location: DebugAddress) -> bool {
+        wrapper::clear_watchpoint(&location);
+        self.debug_points.clear_watchpoint(location)
+    }
+
+    fn clear_debug_points(&mut self) {
+        wrapper::clear_debug_points();
+        self.debug_points.clear();
+    }
+
+    /// Commands are handled between two frames, so recordings always start
+    /// and stop on a frame boundary.
+    fn start_recording(&mut self, settings: RecordingSettings) -> bool {
+        if self.game_path.is_none() {
+            return false;
+        }
+
+        match recording::start(settings) {
+            Ok(_) => true,
+            Err(err) => {
+                eprintln!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn stop_recording(&mut self) -> Option<RecordingSummary> {
+        match recording::stop()? {
+            Ok(summary) => Some(summary),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        }
+    }
+
+    fn start_vgm_log(&mut self, path: PathBuf) -> bool {
+        if self.game_path.is_none() {
+            return false;
+        }
+
+        match vgm::start(path, &wrapper::get_apu_registers(), wrapper::get_cycle_count()) {
+            Ok(_) => true,
+            Err(err) => {
+                eprintln!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn stop_vgm_log(&mut self) -> Option<VgmSummary> {
+        match vgm::stop(wrapper::get_cycle_count())? {
+            Ok(summary) => Some(summary),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        }
+    }
+
+    fn start_audio_capture(&mut self, settings: CaptureSettings) -> bool {
+        match capture::start(settings) {
+            Ok(_) => true,
+            Err(err) => {
+                eprintln!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn start_stems_recording(&mut self, path: PathBuf) -> bool {
+        match stems::start(path) {
+            Ok(_) => true,
+            Err(err) => {
+                eprintln!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn get_apu_state(&self) -> Option<ApuState> {
+        if self.game_path.is_none() {
             return None;
         }
 
-        wrapper::evaluate(request)
+        Some(ApuState::decode(&wrapper::get_apu_registers()))
     }
 
-    fn read_memory_and_send(&mut self, request: String, sender: Sender<Option<String>>) {
-        sender.send(self.read_memory(request)).unwrap()
+    fn load_gbs(&mut self, file: GbsFile, settings: GbsSettings) -> Option<GbsStatus> {
+        let track = file.first_track;
+        self.gbs = Some(GbsPlayback::new(file, settings));
+        let status = self.play_gbs_track(track);
+        if status.is_none() {
+            self.gbs = None;
+        }
+        status
     }
 
-    fn read_bulk_save_memory(&mut self, offset: usize, length: usize) -> Option<Vec<u8>> {
-        wrapper::get_sram(offset, length).ok()
+    fn play_gbs_track(&mut self, track: u8) -> Option<GbsStatus> {
+        let playback = self.gbs.as_mut()?;
+        let game = match GbsGame::new(playback.file(), track) {
+            Ok(game) => game,
+            Err(err) => {
+                eprintln!("{}", err);
+                return None;
+            }
+        };
+
+        playback.set_track(track);
+        self.load_game(Box::new(game));
+        self.gbs_status()
+    }
+
+    fn select_gbs_track(&mut self, selection: GbsTrackSelection) -> Option<GbsStatus> {
+        let track = self.gbs.as_ref()?.select(selection);
+        self.play_gbs_track(track)
     }
 
-    fn read_bulk_save_memory_and_send(&mut self, offset: usize, length: usize, sender: Sender<Option<Vec<u8>>>) {
-        if self.game_path.is_none() || !self.running {
-            sender.send(Some(Vec::new())).unwrap();
+    fn gbs_status(&self) -> Option<GbsStatus> {
+        let elapsed = self.emulated_frames as f32 / FRAME_RATE;
+        self.gbs.as_ref().map(|playback| playback.status(elapsed))
+    }
+
+    /// Moves on to the next track once the current one played for its
+    /// length, and stops after the last one.
+    fn advance_gbs_track(&mut self) {
+        let (track, length, track_count) = match self.gbs.as_ref() {
+            Some(playback) => match playback.length() {
+                Some(length) => (playback.track(), length, playback.file().track_count),
+                None => return
+            },
+            None => return
+        };
+
+        if (self.emulated_frames as f32) < length * FRAME_RATE {
             return;
         }
 
-        sender.send(self.read_bulk_save_memory(offset, length)).unwrap()
+        if track < track_count {
+            self.play_gbs_track(track + 1);
+        } else {
+            self.running = false;
+        }
+    }
+
+    fn can_step(&self) -> bool {
+        self.game_path.is_some() && !self.running
+    }
+
+    fn get_cpu_state(&mut self) -> Option<CpuState> {
+        if self.game_path.is_none() {
+            return None;
+        }
+
+        Some(wrapper::get_cpu_state())
+    }
+
+    fn set_cpu_state(&mut self, update: CpuStateUpdate) -> Option<CpuState> {
+        if !self.can_step() {
+            return None;
+        }
+
+        let state = wrapper::get_cpu_state().apply(&update);
+        wrapper::set_cpu_state(&state);
+
+        Some(wrapper::get_cpu_state())
+    }
+
+    fn step_frames(&mut self, count: u32) -> Option<StepResult> {
+        if !self.can_step() {
+            return None;
+        }
+
+        let mut steps = 0;
+        while steps < count {
+            let stopped = self.emulate_frame();
+            steps = steps + 1;
+
+            if stopped {
+                break;
+            }
+        }
+
+        Some(StepResult {
+            steps,
+            completed: steps == count,
+            registers: wrapper::get_registers()
+        })
+    }
+
+    fn step_instructions(&mut self, count: u32) -> Option<StepResult> {
+        if !self.can_step() {
+            return None;
+        }
+
+        let (steps, registers) = wrapper::step_instructions(count);
+
+        Some(StepResult {
+            steps,
+            completed: steps == count,
+            registers
+        })
+    }
+
+    fn run_until(&mut self, pc: u16, max_instructions: u32) -> Option<StepResult> {
+        if !self.can_step() {
+            return None;
+        }
+
+        let (reached, executed, registers) = wrapper::run_until(pc, max_instructions);
+
+        Some(StepResult {
+            steps: executed,
+            completed: reached,
+            registers
+        })
     }
 
     fn write_memory(&mut self, request: String) -> Option<String> {
@@ -224,6 +796,25 @@ impl SameBoyEmulator {
     }
 }
 
+fn watchpoint_flags(watchpoint: &Watchpoint) -> u8 {
+    let mut flags = 0;
+    if watchpoint.read {
+        flags = flags | wrapper::WATCHPOINT_READ;
+    }
+    if watchpoint.write {
+        flags = flags | wrapper::WATCHPOINT_WRITE;
+    }
+    flags
+}
+
+fn evaluate_read(request: &str) -> Option<String> {
+    if RE_IS_ASSIGNMENT.find(request).is_some() {
+        return None;
+    }
+
+    wrapper::evaluate(request.to_owned())
+}
+
 const FRAME_RATE: f32 = 59.7154;
 
 impl super::Emulator for SameBoyEmulator {
@@ -232,8 +823,10 @@ impl super::Emulator for SameBoyEmulator {
         wrapper::set_environment_cb(environment::environment_callback);
         wrapper::set_input_poll_cb(input::input_poll);
         wrapper::set_input_state_cb(input::input_state);
-        wrapper::set_audio_sample_cb(audio::audio_sample);
-        wrapper::set_video_refresh_cb();
+        wrapper::set_audio_sample_batch_cb(audio::audio_sample_batch);
+        wrapper::set_channel_sample_cb(audio::channel_samples);
+        wrapper::set_apu_write_cb(audio::apu_write);
+        wrapper::set_video_refresh_cb(video::video_refresh);
         wrapper::init();
     }
 
@@ -244,20 +837,65 @@ impl super::Emulator for SameBoyEmulator {
             RunFrame => self.run_frame(),
             RunStealth(jump_location, state, sender) => self.run_stealth_and_send(jump_location, state, sender),
             ReadMemory(request, sender) => self.read_memory_and_send(request, sender),
-            ReadBulkSaveMemory(offset, length, sender) => {self.read_bulk_save_memory_and_send(offset, length, sender)}
+            ReadBulkMemory(region, offset, length, sender) => self.read_bulk_memory_and_send(region, offset, length, sender),
+            WriteBulkMemory(region, offset, data, sender) => sender.send(self.write_bulk_memory(region, offset, data)).unwrap(),
             WriteMemory(request, sender) => self.write_memory_and_send(request, sender),
+            Watch(id, request, sender) => self.watch(id, request, sender),
+            Unwatch(id) => { self.watches.remove(&id); },
+            SetBreakpoint(breakpoint, sender) => sender.send(self.set_breakpoint(breakpoint)).unwrap(),
+            ClearBreakpoint(location, sender) => sender.send(self.clear_breakpoint(location)).unwrap(),
+            SetWatchpoint(watchpoint, sender) => sender.send(self.set_watchpoint(watchpoint)).unwrap(),
+            ClearWatchpoint(location, sender) => sender.send(self.clear_watchpoint(location)).unwrap(),
+            ClearDebugPoints => self.clear_debug_points(),
+            ListDebugPoints(sender) => sender.send(self.debug_points.clone()).unwrap(),
+            GetCpuState(sender) => sender.send(self.get_cpu_state()).unwrap(),
+            SetCpuState(update, sender) => sender.send(self.set_cpu_state(update)).unwrap(),
+            StepFrames(count, sender) => sender.send(self.step_frames(count)).unwrap(),
+            StepInstructions(count, sender) => sender.send(self.step_instructions(count)).unwrap(),
+            RunUntil(pc, max_instructions, sender) => sender.send(self.run_until(pc, max_instructions)).unwrap(),
+            StartRecording(settings, sender) => sender.send(self.start_recording(settings)).unwrap(),
+            StopRecording(sender) => sender.send(self.stop_recording()).unwrap(),
+            StartVgmLog(path, sender) => sender.send(self.start_vgm_log(path)).unwrap(),
+            StopVgmLog(sender) => sender.send(self.stop_vgm_log()).unwrap(),
+            GetApuState(sender) => sender.send(self.get_apu_state()).unwrap(),
+            StartAudioCapture(settings, sender) => sender.send(self.start_audio_capture(settings)).unwrap(),
+            StopAudioCapture(sender) => sender.send(capture::stop()).unwrap(),
+            GetAudioCaptureStatus(sender) => sender.send(capture::status()).unwrap(),
+            StartStemsRecording(path, sender) => sender.send(self.start_stems_recording(path)).unwrap(),
+            StopStemsRecording(sender) => sender.send(stems::stop()).unwrap(),
+            SetRewind(settings) => self.set_rewind(settings),
+            Rewind(amount, sender) => sender.send(self.rewind(amount)).unwrap(),
+            ListCheats(sender) => sender.send(self.list_cheats()).unwrap(),
+            AddCheat(code, description, enabled, sender) => sender.send(self.add_cheat(code, description, enabled)).unwrap(),
+            SetCheatEnabled(code, enabled, sender) => sender.send(self.set_cheat_enabled(code, enabled)).unwrap(),
+            RemoveCheat(code, sender) => sender.send(self.remove_cheat(code)).unwrap(),
             GetScreenData(sender) => sender.send(wrapper::get_screen_data()).unwrap(),
             Input((input, pressed)) => {
                let sb_input = wrapper::SameboyJoypadInput::from(input);
                input::store_input(sb_input, pressed);
             }
             Save => self.save(),
+            SaveState(sender) => sender.send(self.save_state()).unwrap(),
+            LoadState(state, sender) => sender.send(self.load_state(state)).unwrap(),
+            ListStateSlots(sender) => sender.send(self.list_state_slots()).unwrap(),
+            CreateStateSlot(name, with_thumbnail, sender) => sender.send(self.create_state_slot(name, with_thumbnail)).unwrap(),
+            LoadStateSlot(name, sender) => sender.send(self.load_state_slot(name)).unwrap(),
+            RenameStateSlot(from, to, sender) => sender.send(self.rename_state_slot(from, to)).unwrap(),
+            DeleteStateSlot(name, sender) => sender.send(self.delete_state_slot(name)).unwrap(),
+            GetStateSlotThumbnail(name, sender) => sender.send(self.get_state_slot_thumbnail(name)).unwrap(),
             Stop => return false,
-            LoadGame(game) => self.load_game(game),
+            LoadGame(game) => {
+                self.gbs = None;
+                self.load_game(game);
+            },
+            LoadGbs(file, settings, sender) => sender.send(self.load_gbs(*file, settings)).unwrap(),
+            SelectGbsTrack(selection, sender) => sender.send(self.select_gbs_track(selection)).unwrap(),
+            GetGbsStatus(sender) => sender.send(self.gbs_status()).unwrap(),
             UnloadGame => self.unload_game(),
             LoadSave(save) => self.load_save(save),
             Pause => self.running = false,
             Resume => self.running = true,
+            Reset(kind) => self.reset(kind),
             Burst(commands, sender) => self.burst(commands, sender)
         };
 
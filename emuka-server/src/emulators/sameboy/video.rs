@@ -0,0 +1,7 @@
+use crate::video::{clip, recording, stream};
+
+pub fn video_refresh(data: &[u32], width: u32, height: u32, _pitch: u64) {
+    recording::write_frame(data, width, height);
+    clip::push(data, width, height);
+    stream::publish(data, width, height);
+}
@@ -2,7 +2,7 @@ use std::{collections::HashMap, convert::TryFrom, ffi::{CStr, CString, c_void},
 use lazy_static::lazy_static;
 use num_enum::TryFromPrimitive;
 use eyre::*;
-use crate::emulators::ScreenData;
+use crate::emulators::{EmulatorMemoryRegion, ScreenData, debug::{CpuRegisters, CpuState, DebugAddress, DebugStopKind}};
 
 use super::bindings::bindings::{self, size_t};
 
@@ -90,16 +90,21 @@ impl From<crate::emulators::EmulatorJoypadInput> for SameboyJoypadInput {
 pub type EnvironmentCallback = fn(cmd: &EnvironmentCallbackCmd, data: &mut EnvironmentCallbackData) -> bool;
 pub type InputPollCallback = fn();
 pub type InputStateCallback = fn() -> i16;
-pub type AudioSampleCallback = fn(i16, i16);
+/// Gets interleaved stereo samples, returns how many frames were consumed.
+pub type AudioSampleBatchCallback = fn(&[i16]) -> usize;
 pub type VideoRefreshCallback = fn(&[u32], u32, u32, u64);
+pub type ChannelSampleCallback = fn(&[i16; 8]);
+pub type ApuWriteCallback = fn(u8, u8, u64);
 
 
 lazy_static! {
     static ref ENVIRON_CALLBACK_GLOBAL: RwLock<Option<EnvironmentCallback>> = RwLock::new(None);
     static ref INPUT_POLL_CALLBACK_GLOBAL: RwLock<Option<InputPollCallback>> = RwLock::new(None);
     static ref INPUT_STATE_CALLBACK_GLOBAL: RwLock<Option<InputStateCallback>> = RwLock::new(None);
-    static ref AUDIO_SAMPLE_CALLBACK_GLOBAL: RwLock<Option<AudioSampleCallback>> = RwLock::new(None);
+    static ref AUDIO_SAMPLE_BATCH_CALLBACK_GLOBAL: RwLock<Option<AudioSampleBatchCallback>> = RwLock::new(None);
     static ref VIDEO_REFRESH_CALLBACK_GLOBAL: RwLock<Option<VideoRefreshCallback>> = RwLock::new(None);
+    static ref CHANNEL_SAMPLE_CALLBACK_GLOBAL: RwLock<Option<ChannelSampleCallback>> = RwLock::new(None);
+    static ref APU_WRITE_CALLBACK_GLOBAL: RwLock<Option<ApuWriteCallback>> = RwLock::new(None);
 }
 
 unsafe fn interpret_cstring(ptr: *const i8) -> Option<String> {
@@ -322,6 +327,12 @@ pub fn load_game(game_info: &GameInfo) -> bool {
     }
 }
 
+pub fn reset() {
+    unsafe {
+        bindings::retro_reset();
+    }
+}
+
 pub fn run_frame() {
     unsafe {
         bindings::retro_run();
@@ -394,41 +405,154 @@ pub fn set_input_state_cb(cb: InputStateCallback) {
 }
 
 
-fn audio_sample_call(cb: AudioSampleCallback, left: i16, right: i16) {
-    let cb_result = catch_unwind(|| cb(left, right));
+fn audio_sample_batch_call(cb: AudioSampleBatchCallback, data: &[i16]) -> usize {
+    let cb_result = catch_unwind(|| cb(data));
 
     match cb_result {
         Ok(result) => result,
         Err(err) => {
             println!("{:?}", err);
+            0
         }
     }
 }
 
+unsafe extern "C" fn audio_sample_batch_cb(data: *const i16, frames: bindings::size_t) -> bindings::size_t {
+    if data.is_null() {
+        return 0;
+    }
+
+    let cb_lock_result = AUDIO_SAMPLE_BATCH_CALLBACK_GLOBAL.read();
+    match cb_lock_result {
+        Err(_) => 0,
+        Ok(cb_lock) => {
+            match *cb_lock {
+                None => 0,
+                Some(cb) => {
+                    let data = std::slice::from_raw_parts(data, frames as usize * 2);
+                    audio_sample_batch_call(cb, data) as bindings::size_t
+                }
+            }
+        }
+    }
+}
+
+/// Single samples, should the core send any, go through the batch callback too.
 unsafe extern "C" fn audio_sample_cb(left: i16, right: i16) {
-    let cb_lock_result = AUDIO_SAMPLE_CALLBACK_GLOBAL.read();
+    let frame = [left, right];
+    audio_sample_batch_cb(frame.as_ptr(), 1);
+}
+
+
+pub fn set_audio_sample_batch_cb(cb: AudioSampleBatchCallback) {
+    {
+        let mut lock = AUDIO_SAMPLE_BATCH_CALLBACK_GLOBAL.write().unwrap();
+        *lock = Some(cb);
+    }
+
+    unsafe {
+        bindings::retro_set_audio_sample_batch(Some(audio_sample_batch_cb));
+        bindings::retro_set_audio_sample(Some(audio_sample_cb));
+    }
+}
+
+
+fn channel_sample_call(cb: ChannelSampleCallback, samples: &[i16; 8]) {
+    let cb_result = catch_unwind(|| cb(samples));
+
+    match cb_result {
+        Ok(result) => result,
+        Err(err) => {
+            println!("{:?}", err);
+        }
+    }
+}
+
+unsafe extern "C" fn channel_sample_cb(samples: *const i16) {
+    if samples.is_null() {
+        return;
+    }
+
+    let cb_lock_result = CHANNEL_SAMPLE_CALLBACK_GLOBAL.read();
     match cb_lock_result {
         Err(_) => (),
         Ok(cb_lock) => {
             match *cb_lock {
                 None => (),
                 Some(cb) => {
-                    audio_sample_call(cb, left, right)
+                    channel_sample_call(cb, &*(samples as *const [i16; 8]))
                 }
             }
         }
     }
 }
 
+/// Per-channel output is produced by the fork's APU shim, once per output sample.
+pub fn set_channel_sample_cb(cb: ChannelSampleCallback) {
+    {
+        let mut lock = CHANNEL_SAMPLE_CALLBACK_GLOBAL.write().unwrap();
+        *lock = Some(cb);
+    }
+
+    unsafe {
+        bindings::emuka_set_channel_sample_callback(Some(channel_sample_cb));
+    }
+}
+
+
+fn apu_write_call(cb: ApuWriteCallback, address: u8, value: u8, cycles: u64) {
+    let cb_result = catch_unwind(|| cb(address, value, cycles));
 
-pub fn set_audio_sample_cb(cb: AudioSampleCallback) {
+    match cb_result {
+        Ok(result) => result,
+        Err(err) => {
+            println!("{:?}", err);
+        }
+    }
+}
+
+unsafe extern "C" fn apu_write_cb(address: u8, value: u8, cycles: u64) {
+    let cb_lock_result = APU_WRITE_CALLBACK_GLOBAL.read();
+    match cb_lock_result {
+        Err(_) => (),
+        Ok(cb_lock) => {
+            match *cb_lock {
+                None => (),
+                Some(cb) => {
+                    apu_write_call(cb, address, value, cycles)
+                }
+            }
+        }
+    }
+}
+
+/// Called for every write to $FF10-$FF3F with the low byte of the address,
+/// and the cycle count at a constant 4194304 Hz, even in double speed mode.
+pub fn set_apu_write_cb(cb: ApuWriteCallback) {
     {
-        let mut lock = AUDIO_SAMPLE_CALLBACK_GLOBAL.write().unwrap();
+        let mut lock = APU_WRITE_CALLBACK_GLOBAL.write().unwrap();
         *lock = Some(cb);
     }
 
     unsafe {
-        bindings::retro_set_audio_sample(Some(audio_sample_cb));
+        bindings::emuka_set_apu_write_callback(Some(apu_write_cb));
+    }
+}
+
+/// Current values of $FF10-$FF3F.
+pub fn get_apu_registers() -> [u8; 0x30] {
+    let mut registers = [0u8; 0x30];
+
+    unsafe {
+        bindings::emuka_get_apu_registers(registers.as_mut_ptr());
+    }
+
+    registers
+}
+
+pub fn get_cycle_count() -> u64 {
+    unsafe {
+        bindings::emuka_get_cycle_count()
     }
 }
 
@@ -448,24 +572,56 @@ lazy_static!{
 }
 
 
-unsafe extern "C" fn video_refresh_cb(data: *const c_void, width: c_uint, height: c_uint, _pitch: size_t) {
+fn video_refresh_call(cb: VideoRefreshCallback, data: &[u32], width: u32, height: u32, pitch: u64) {
+    let cb_result = catch_unwind(|| cb(data, width, height, pitch));
+
+    match cb_result {
+        Ok(result) => result,
+        Err(err) => {
+            println!("{:?}", err);
+        }
+    }
+}
+
+unsafe extern "C" fn video_refresh_cb(data: *const c_void, width: c_uint, height: c_uint, pitch: size_t) {
+    if data.is_null() {
+        return;
+    }
+
+    let slc: &[u32] = std::slice::from_raw_parts(data.cast(), (width * height) as usize);
+
     match SCREEN_DATA.lock() {
         Ok(mut lock) => {
-            if !data.is_null() {
-                (*lock).height = height;
-                (*lock).width = width;
-                let slc: &[u32] = std::slice::from_raw_parts(data.cast(), (width * height) as usize);
-                (*lock).data = Vec::from(slc);
-            }
+            (*lock).height = height;
+            (*lock).width = width;
+            (*lock).data = Vec::from(slc);
         }
         Err(err) => {
             eprintln!("{}", err)
         }
     }
+
+    let cb_lock_result = VIDEO_REFRESH_CALLBACK_GLOBAL.read();
+    match cb_lock_result {
+        Err(_) => (),
+        Ok(cb_lock) => {
+            match *cb_lock {
+                None => (),
+                Some(cb) => {
+                    video_refresh_call(cb, slc, width, height, pitch as u64)
+                }
+            }
+        }
+    }
 }
 
 
-pub fn set_video_refresh_cb() {
+pub fn set_video_refresh_cb(cb: VideoRefreshCallback) {
+    {
+        let mut lock = VIDEO_REFRESH_CALLBACK_GLOBAL.write().unwrap();
+        *lock = Some(cb);
+    }
+
     unsafe {
         bindings::retro_set_video_refresh(Some(video_refresh_cb));
     }
@@ -539,6 +695,56 @@ pub fn save <P: AsRef<Path>> (path: P) {
     unsafe { CString::from_raw(cstr_ptr) };
 }
 
+pub fn serialize() -> Result<Vec<u8>> {
+    unsafe {
+        let size = bindings::retro_serialize_size() as usize;
+
+        if size == 0 {
+            return Err(Report::msg("Nothing to serialize"));
+        }
+
+        let mut data = vec![0u8; size];
+
+        if !bindings::retro_serialize(data.as_mut_ptr().cast(), size as size_t) {
+            return Err(Report::msg("Could not serialize state"));
+        }
+
+        Ok(data)
+    }
+}
+
+pub fn unserialize(data: &[u8]) -> Result<()> {
+    unsafe {
+        let size = bindings::retro_serialize_size() as usize;
+
+        if data.len() != size {
+            return Err(Report::msg("State size mismatch"));
+        }
+
+        if !bindings::retro_unserialize(data.as_ptr().cast(), data.len() as size_t) {
+            return Err(Report::msg("Could not unserialize state"));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn cheat_reset() {
+    unsafe {
+        bindings::retro_cheat_reset();
+    }
+}
+
+pub fn cheat_set(index: u32, enabled: bool, code: &str) -> Result<()> {
+    let cstring = CString::new(code)?;
+
+    unsafe {
+        bindings::retro_cheat_set(index, enabled, cstring.as_ptr());
+    }
+
+    Ok(())
+}
+
 pub fn set_audio_frequency(frequency: u32) {
     unsafe {
         bindings::emuka_set_audio_frequency(frequency);
@@ -550,19 +756,21 @@ struct SameBoyRegisters {
     af: u16,
     bc: u16,
     de: u16,
-    hf: u16,
+    hl: u16,
     sp: u16
 }
 
 impl SameBoyRegisters {
     pub fn to_array(self) -> [u16; 5] {
-        [self.af, self.bc, self.de, self.hf, self.sp]
+        [self.af, self.bc, self.de, self.hl, self.sp]
     }
 
     pub fn fill_state(registers: [u16; 5], state: &mut HashMap<String, u32>) {
         state.insert("af".to_owned(), registers[0] as u32);
         state.insert("bc".to_owned(), registers[1] as u32);
         state.insert("de".to_owned(), registers[2] as u32);
+        state.insert("hl".to_owned(), registers[3] as u32);
+        // Kept for clients written against the original, misnamed key
         state.insert("hf".to_owned(), registers[3] as u32);
         state.insert("sp".to_owned(), registers[4] as u32);
     }
@@ -572,7 +780,7 @@ impl SameBoyRegisters {
             af: state.get("af").unwrap_or(&0).clone() as u16,
             bc: state.get("bc").unwrap_or(&0).clone() as u16,
             de: state.get("de").unwrap_or(&0).clone() as u16,
-            hf: state.get("hf").unwrap_or(&0).clone() as u16,
+            hl: state.get("hl").or(state.get("hf")).unwrap_or(&0).clone() as u16,
             sp: state.get("sp").unwrap_or(&0).clone() as u16
         }
     }
@@ -592,6 +800,161 @@ pub fn run_stealth(jump_location: u16, state: &mut HashMap<String, u32>) -> Resu
     Ok(())
 }
 
+impl From<[u16; 6]> for CpuRegisters {
+    fn from(registers: [u16; 6]) -> Self {
+        Self {
+            af: registers[0],
+            bc: registers[1],
+            de: registers[2],
+            hl: registers[3],
+            sp: registers[4],
+            pc: registers[5]
+        }
+    }
+}
+
+// SameBoy's debugger reports "no bank" as -1, as in `evaluate`.
+fn debug_bank(location: &DebugAddress) -> u16 {
+    location.bank.unwrap_or(u16::MAX)
+}
+
+fn debug_condition(condition: Option<&str>) -> Result<Option<CString>> {
+    Ok(match condition {
+        Some(condition) => Some(CString::new(condition)?),
+        None => None
+    })
+}
+
+pub fn set_breakpoint(location: &DebugAddress, condition: Option<&str>) -> Result<()> {
+    let condition = debug_condition(condition)?;
+    let condition_ptr = condition.as_ref().map_or(std::ptr::null(), |condition| condition.as_ptr());
+
+    let success = unsafe {
+        bindings::emuka_set_breakpoint(debug_bank(location), location.address, condition_ptr)
+    };
+
+    if success {
+        Ok(())
+    } else {
+        Err(Report::msg("Could not set breakpoint"))
+    }
+}
+
+pub fn clear_breakpoint(location: &DebugAddress) -> bool {
+    unsafe {
+        bindings::emuka_clear_breakpoint(debug_bank(location), location.address)
+    }
+}
+
+pub const WATCHPOINT_READ: u8 = 1;
+pub const WATCHPOINT_WRITE: u8 = 2;
+
+pub fn set_watchpoint(location: &DebugAddress, flags: u8, condition: Option<&str>) -> Result<()> {
+    let condition = debug_condition(condition)?;
+    let condition_ptr = condition.as_ref().map_or(std::ptr::null(), |condition| condition.as_ptr());
+
+    let success = unsafe {
+        bindings::emuka_set_watchpoint(debug_bank(location), location.address, flags, condition_ptr)
+    };
+
+    if success {
+        Ok(())
+    } else {
+        Err(Report::msg("Could not set watchpoint"))
+    }
+}
+
+pub fn clear_watchpoint(location: &DebugAddress) -> bool {
+    unsafe {
+        bindings::emuka_clear_watchpoint(debug_bank(location), location.address)
+    }
+}
+
+pub fn clear_debug_points() {
+    unsafe {
+        bindings::emuka_clear_debug_points();
+    }
+}
+
+/// Whether the last frame was interrupted by a breakpoint or a watchpoint.
+pub fn debug_stopped() -> Option<(DebugStopKind, u16, CpuRegisters)> {
+    let mut kind: u8 = 0;
+    let mut address: u16 = 0;
+    let mut registers = [0u16; 6];
+
+    let stopped = unsafe {
+        bindings::emuka_debug_stopped(&mut kind, &mut address, registers.as_mut_ptr())
+    };
+
+    if !stopped {
+        return None;
+    }
+
+    let kind = match kind {
+        WATCHPOINT_READ => DebugStopKind::ReadWatchpoint,
+        WATCHPOINT_WRITE => DebugStopKind::WriteWatchpoint,
+        _ => DebugStopKind::Breakpoint
+    };
+
+    Some((kind, address, CpuRegisters::from(registers)))
+}
+
+pub fn get_registers() -> CpuRegisters {
+    let mut registers = [0u16; 6];
+
+    unsafe {
+        bindings::emuka_get_registers(registers.as_mut_ptr());
+    }
+
+    CpuRegisters::from(registers)
+}
+
+pub fn get_cpu_state() -> CpuState {
+    let mut registers = [0u16; 6];
+    let mut ime = false;
+    let mut halted = false;
+    let mut stopped = false;
+
+    unsafe {
+        bindings::emuka_get_cpu_state(registers.as_mut_ptr(), &mut ime, &mut halted, &mut stopped);
+    }
+
+    CpuState::new(CpuRegisters::from(registers), ime, halted, stopped)
+}
+
+pub fn set_cpu_state(state: &CpuState) {
+    let registers = state.registers.to_array();
+
+    unsafe {
+        bindings::emuka_set_cpu_state(registers.as_ptr(), state.ime, state.halted, state.stopped);
+    }
+}
+
+/// Runs up to `count` instructions, stopping early on breakpoints.
+/// Returns how many were executed.
+pub fn step_instructions(count: u32) -> (u32, CpuRegisters) {
+    let mut registers = [0u16; 6];
+
+    let executed = unsafe {
+        bindings::emuka_step_instructions(count, registers.as_mut_ptr())
+    };
+
+    (executed, CpuRegisters::from(registers))
+}
+
+/// Runs until PC reaches `pc`, or `max_instructions` were executed. Returns
+/// whether `pc` was reached and how many instructions ran.
+pub fn run_until(pc: u16, max_instructions: u32) -> (bool, u32, CpuRegisters) {
+    let mut registers = [0u16; 6];
+    let mut executed: u32 = 0;
+
+    let reached = unsafe {
+        bindings::emuka_run_until(pc, max_instructions, &mut executed, registers.as_mut_ptr())
+    };
+
+    (reached, executed, CpuRegisters::from(registers))
+}
+
 pub fn evaluate(request: String) -> Option<String> {
     let mut result: u16 = 0;
     let mut bank: u16 = 0;
@@ -609,19 +972,43 @@ pub fn evaluate(request: String) -> Option<String> {
     }
 }
 
-pub fn get_sram(offset: usize, length: usize) -> Result<Vec<u8>> {
-    let sram = unsafe {
-        let id = bindings::RETRO_MEMORY_SAVE_RAM;
+fn memory_id(region: EmulatorMemoryRegion) -> c_uint {
+    match region {
+        EmulatorMemoryRegion::SaveRam => bindings::RETRO_MEMORY_SAVE_RAM,
+        EmulatorMemoryRegion::Rtc => bindings::RETRO_MEMORY_RTC,
+        EmulatorMemoryRegion::SystemRam => bindings::RETRO_MEMORY_SYSTEM_RAM,
+        EmulatorMemoryRegion::VideoRam => bindings::RETRO_MEMORY_VIDEO_RAM
+    }
+}
 
-        let ptr: *const u8 = bindings::retro_get_memory_data(id).cast();
-        let size = bindings::retro_get_memory_size(id) as usize;
+unsafe fn memory_region<'a>(region: EmulatorMemoryRegion, offset: usize, length: usize) -> Result<&'a mut [u8]> {
+    let id = memory_id(region);
 
-        if offset + length >= size {
-            return Err(Report::msg("Offset and length too large"));           
-        }
+    let ptr: *mut u8 = bindings::retro_get_memory_data(id).cast();
+    let size = bindings::retro_get_memory_size(id) as usize;
 
-        std::slice::from_raw_parts(ptr, size)
+    if ptr.is_null() || size == 0 {
+        return Err(Report::msg(format!("Memory region {:?} is not available", region)));
+    }
+
+    match offset.checked_add(length) {
+        Some(end) if end <= size => {},
+        _ => return Err(Report::msg("Offset and length too large"))
     };
 
-    return Ok(sram[offset..(offset+length)].to_owned())
-}
\ No newline at end of file
+    let memory = std::slice::from_raw_parts_mut(ptr, size);
+    Ok(&mut memory[offset..(offset + length)])
+}
+
+pub fn get_memory(region: EmulatorMemoryRegion, offset: usize, length: usize) -> Result<Vec<u8>> {
+    let memory = unsafe { memory_region(region, offset, length)? };
+
+    Ok(memory.to_owned())
+}
+
+pub fn set_memory(region: EmulatorMemoryRegion, offset: usize, data: &[u8]) -> Result<()> {
+    let memory = unsafe { memory_region(region, offset, data.len())? };
+    memory.copy_from_slice(data);
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use tokio::sync::mpsc::UnboundedSender;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WatchRequest {
+    pub expressions: Vec<String>,
+    /// Minimum number of frames between two updates.
+    #[serde(default)]
+    pub throttle: u32
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct WatchUpdate {
+    pub frame: u64,
+    pub values: HashMap<String, Option<String>>
+}
+
+#[derive(Debug)]
+pub struct WatchSubscription {
+    expressions: Vec<String>,
+    throttle: u64,
+    last_values: HashMap<String, Option<String>>,
+    pending: HashMap<String, Option<String>>,
+    last_sent: Option<u64>,
+    sender: UnboundedSender<WatchUpdate>
+}
+
+impl WatchSubscription {
+    pub fn new(request: WatchRequest, sender: UnboundedSender<WatchUpdate>) -> Self {
+        Self {
+            expressions: request.expressions,
+            throttle: request.throttle as u64,
+            last_values: HashMap::new(),
+            pending: HashMap::new(),
+            last_sent: None,
+            sender
+        }
+    }
+
+    /// Evaluates every expression and pushes the ones that changed, if the
+    /// throttle allows it. Returns `false` once nobody is listening anymore.
+    pub fn update<F: FnMut(&str) -> Option<String>>(&mut self, frame: u64, mut evaluate: F) -> bool {
+        for expression in self.expressions.iter() {
+            let value = evaluate(expression);
+
+            if self.last_values.get(expression) != Some(&value) {
+                self.last_values.insert(expression.clone(), value.clone());
+                self.pending.insert(expression.clone(), value);
+            }
+        }
+
+        if self.pending.is_empty() {
+            return !self.sender.is_closed();
+        }
+
+        let throttled = match self.last_sent {
+            Some(last_sent) => frame.saturating_sub(last_sent) < self.throttle,
+            None => false
+        };
+
+        if throttled {
+            return true;
+        }
+
+        self.last_sent = Some(frame);
+        let update = WatchUpdate {
+            frame,
+            values: std::mem::take(&mut self.pending)
+        };
+
+        self.sender.send(update).is_ok()
+    }
+}
@@ -0,0 +1,443 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use eyre::{Report, Result};
+
+use crate::{game::Game, states::{data_directory, rom_hash}};
+
+const GBS_HEADER_LENGTH: usize = 0x70;
+const ROM_BANK_LENGTH: usize = 0x4000;
+
+/// Where the generated driver lives, right after the cartridge header.
+const DRIVER_ADDRESS: usize = 0x150;
+
+const TAC_TIMER_ENABLE: u8 = 0x04;
+const INTERRUPT_VBLANK: u8 = 0x01;
+const INTERRUPT_TIMER: u8 = 0x04;
+
+/// MBC5 with RAM, as some rips bank switch and use cartridge RAM.
+const CARTRIDGE_TYPE: u8 = 0x1A;
+const RAM_SIZE_32K: u8 = 0x03;
+
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E
+];
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_string(data: &[u8], offset: usize) -> String {
+    let field = &data[offset..(offset + 32)];
+    let end = field.iter().position(|byte| *byte == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).trim().to_owned()
+}
+
+/// A parsed `.gbs` music rip.
+#[derive(Debug, Clone)]
+pub struct GbsFile {
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+    pub track_count: u8,
+    /// 1-based, like every track number in this module.
+    pub first_track: u8,
+    load_address: u16,
+    init_address: u16,
+    play_address: u16,
+    stack_pointer: u16,
+    timer_modulo: u8,
+    timer_control: u8,
+    code: Vec<u8>,
+    hash: String
+}
+
+impl GbsFile {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() <= GBS_HEADER_LENGTH || &data[0..3] != b"GBS" {
+            return Err(Report::msg("Not a GBS file"));
+        }
+
+        if data[3] != 1 {
+            return Err(Report::msg(format!("Unsupported GBS version {}", data[3])));
+        }
+
+        let track_count = data[4];
+        let first_track = data[5];
+        if track_count == 0 || first_track == 0 || first_track > track_count {
+            return Err(Report::msg("Invalid track numbers"));
+        }
+
+        let load_address = read_u16(data, 0x06);
+        let code = data[GBS_HEADER_LENGTH..].to_vec();
+        // The driver and interrupt vectors need the first $400 bytes.
+        if load_address < 0x400 || load_address >= 0x8000 {
+            return Err(Report::msg(format!("Unsupported load address ${:04X}", load_address)));
+        }
+
+        Ok(Self {
+            title: read_string(data, 0x10),
+            author: read_string(data, 0x30),
+            copyright: read_string(data, 0x50),
+            track_count,
+            first_track,
+            load_address,
+            init_address: read_u16(data, 0x08),
+            play_address: read_u16(data, 0x0A),
+            stack_pointer: read_u16(data, 0x0C),
+            timer_modulo: data[0x0E],
+            timer_control: data[0x0F],
+            code,
+            hash: rom_hash(data)
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    /// Whether `play` is driven by the timer rather than by VBlank.
+    fn uses_timer(&self) -> bool {
+        self.timer_control & TAC_TIMER_ENABLE != 0
+    }
+
+    fn driver(&self, track: u8) -> Vec<u8> {
+        let [stack_low, stack_high] = self.stack_pointer.to_le_bytes();
+        let [init_low, init_high] = self.init_address.to_le_bytes();
+        let [play_low, play_high] = self.play_address.to_le_bytes();
+        let interrupts = if self.uses_timer() { INTERRUPT_TIMER } else { INTERRUPT_VBLANK };
+
+        vec![
+            0xF3,                               // di
+            0x31, stack_low, stack_high,        // ld sp, stack
+            0x3E, 0x0A, 0xEA, 0x00, 0x00,       // enable cartridge RAM
+            0x3E, 0x01, 0xEA, 0x00, 0x20,       // select ROM bank 1
+            0x3E, 0x80, 0xE0, 0x26,             // power the APU on
+            0x3E, 0xFF, 0xE0, 0x25,             // every channel on both sides
+            0x3E, 0x77, 0xE0, 0x24,             // full master volume
+            0x3E, self.timer_modulo, 0xE0, 0x06,  // TMA
+            0x3E, self.timer_control, 0xE0, 0x07, // TAC
+            0x3E, track - 1,                    // ld a, track (0-based)
+            0xCD, init_low, init_high,          // call init
+            0x3E, interrupts, 0xE0, 0xFF,       // IE
+            0xAF, 0xE0, 0x0F,                   // clear IF
+            0xFB,                               // ei
+            0x76, 0x00,                         // loop: halt
+            0xCD, play_low, play_high,          // call play
+            0x18, 0xF9                          // jr loop
+        ]
+    }
+
+    /// Builds a cartridge that runs `init` for `track` then calls `play` on
+    /// every VBlank or timer interrupt, as a GBS player would.
+    pub fn build_rom(&self, track: u8) -> Result<Vec<u8>> {
+        if track == 0 || track > self.track_count {
+            return Err(Report::msg(format!("Track {} out of range 1-{}", track, self.track_count)));
+        }
+
+        let end = self.load_address as usize + self.code.len();
+        let mut length = 2 * ROM_BANK_LENGTH;
+        let mut size_code = 0u8;
+        while length < end {
+            length = length * 2;
+            size_code = size_code + 1;
+        }
+
+        let mut rom = vec![0xFFu8; length];
+        rom[(self.load_address as usize)..end].copy_from_slice(&self.code);
+
+        // RST vectors jump to their counterparts relative to the load address,
+        // interrupts only have to wake the driver up.
+        for vector in (0..0x40).step_by(8) {
+            let [low, high] = (self.load_address + vector as u16).to_le_bytes();
+            rom[vector..(vector + 3)].copy_from_slice(&[0xC3, low, high]);
+        }
+        for vector in (0x40..0x68).step_by(8) {
+            rom[vector] = 0xD9; // reti
+        }
+
+        let [driver_low, driver_high] = (DRIVER_ADDRESS as u16).to_le_bytes();
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, driver_low, driver_high]);
+        rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+
+        for byte in rom[0x134..0x150].iter_mut() {
+            *byte = 0;
+        }
+        for (byte, character) in rom[0x134..0x143].iter_mut().zip(self.title.bytes().filter(|c| c.is_ascii())) {
+            *byte = character.to_ascii_uppercase();
+        }
+        rom[0x147] = CARTRIDGE_TYPE;
+        rom[0x148] = size_code;
+        rom[0x149] = RAM_SIZE_32K;
+        rom[0x14D] = rom[0x134..0x14D].iter().fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1));
+
+        let driver = self.driver(track);
+        rom[DRIVER_ADDRESS..(DRIVER_ADDRESS + driver.len())].copy_from_slice(&driver);
+
+        let checksum = rom.iter().fold(0u16, |checksum, byte| checksum.wrapping_add(*byte as u16));
+        rom[0x14E..0x150].copy_from_slice(&checksum.to_be_bytes());
+
+        Ok(rom)
+    }
+}
+
+/// One track of a GBS file, as a cartridge the core can load.
+#[derive(Debug)]
+pub struct GbsGame {
+    name: String,
+    data: Vec<u8>,
+    path: PathBuf
+}
+
+impl GbsGame {
+    /// The core only loads games from disk, so the generated cartridge is
+    /// written to the data directory.
+    pub fn new(gbs: &GbsFile, track: u8) -> Result<Self> {
+        let data = gbs.build_rom(track)?;
+        let directory = data_directory().join("gbs").join(&gbs.hash);
+        fs::create_dir_all(&directory)?;
+
+        let path = directory.join(format!("track-{:03}.gb", track));
+        fs::write(&path, &data)?;
+
+        Ok(Self {
+            name: format!("{} - {}", gbs.title, track),
+            data,
+            path
+        })
+    }
+}
+
+impl Game for GbsGame {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn path(&self) -> Option<Box<Path>> {
+        Some(self.path.clone().into_boxed_path())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GbsSettings {
+    /// Seconds each track plays before moving to the next one, forever if `None`.
+    pub default_length: Option<f32>,
+    /// Per-track lengths overriding the default, starting with track 1.
+    /// Lengths of 0 or less fall back to the default.
+    pub lengths: Vec<f32>
+}
+
+#[derive(Debug, Deserialize, Copy, Clone)]
+pub enum GbsTrackSelection {
+    Track(u8),
+    Next,
+    Previous
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct GbsStatus {
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+    pub track_count: u8,
+    pub track: u8,
+    pub elapsed: f32,
+    pub length: Option<f32>
+}
+
+#[derive(Debug)]
+pub struct GbsPlayback {
+    file: GbsFile,
+    settings: GbsSettings,
+    track: u8
+}
+
+impl GbsPlayback {
+    pub fn new(file: GbsFile, settings: GbsSettings) -> Self {
+        Self {
+            track: file.first_track,
+            file,
+            settings
+        }
+    }
+
+    pub fn file(&self) -> &GbsFile {
+        &self.file
+    }
+
+    pub fn track(&self) -> u8 {
+        self.track
+    }
+
+    pub fn set_track(&mut self, track: u8) {
+        self.track = track;
+    }
+
+    /// Resolves `selection` to a track number, wrapping around at both ends.
+    pub fn select(&self, selection: GbsTrackSelection) -> u8 {
+        let count = self.file.track_count;
+        match selection {
+            GbsTrackSelection::Track(track) => track,
+            GbsTrackSelection::Next => if self.track >= count { 1 } else { self.track + 1 },
+            GbsTrackSelection::Previous => if self.track <= 1 { count } else { self.track - 1 }
+        }
+    }
+
+    pub fn length(&self) -> Option<f32> {
+        match self.settings.lengths.get(self.track as usize - 1) {
+            Some(length) if *length > 0.0 => Some(*length),
+            _ => self.settings.default_length
+        }
+    }
+
+    pub fn status(&self, elapsed: f32) -> GbsStatus {
+        GbsStatus {
+            title: self.file.title.clone(),
+            author: self.file.author.clone(),
+            copyright: self.file.copyright.clone(),
+            track_count: self.file.track_count,
+            track: self.track,
+            elapsed,
+            length: self.length()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOAD_ADDRESS: u16 = 0x0400;
+    const INIT_ADDRESS: u16 = 0x0410;
+    const PLAY_ADDRESS: u16 = 0x0420;
+
+    fn gbs_data(code_length: usize, timer_control: u8) -> Vec<u8> {
+        let mut data = vec![0u8; GBS_HEADER_LENGTH];
+        data[0..4].copy_from_slice(b"GBS\x01");
+        data[4] = 3;
+        data[5] = 2;
+        data[0x06..0x08].copy_from_slice(&LOAD_ADDRESS.to_le_bytes());
+        data[0x08..0x0A].copy_from_slice(&INIT_ADDRESS.to_le_bytes());
+        data[0x0A..0x0C].copy_from_slice(&PLAY_ADDRESS.to_le_bytes());
+        data[0x0C..0x0E].copy_from_slice(&0xDFFFu16.to_le_bytes());
+        data[0x0E] = 0xC0;
+        data[0x0F] = timer_control;
+        data[0x10..0x15].copy_from_slice(b"Title");
+        data[0x30..0x36].copy_from_slice(b"Author");
+        data[0x50..0x54].copy_from_slice(b"2021");
+        data.extend((0..code_length).map(|index| index as u8));
+        data
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn parses_header() {
+        let gbs = GbsFile::parse(&gbs_data(16, 0)).unwrap();
+
+        assert_eq!(gbs.title, "Title");
+        assert_eq!(gbs.author, "Author");
+        assert_eq!(gbs.copyright, "2021");
+        assert_eq!(gbs.track_count, 3);
+        assert_eq!(gbs.first_track, 2);
+        assert_eq!(gbs.load_address, LOAD_ADDRESS);
+        assert_eq!(gbs.code.len(), 16);
+        assert!(!gbs.uses_timer());
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(GbsFile::parse(b"GBS").is_err());
+
+        let mut data = gbs_data(16, 0);
+        data[0] = b'X';
+        assert!(GbsFile::parse(&data).is_err());
+
+        let mut data = gbs_data(16, 0);
+        data[3] = 2;
+        assert!(GbsFile::parse(&data).is_err());
+
+        let mut data = gbs_data(16, 0);
+        data[5] = 4;
+        assert!(GbsFile::parse(&data).is_err());
+
+        let mut data = gbs_data(16, 0);
+        data[0x06..0x08].copy_from_slice(&0x0200u16.to_le_bytes());
+        assert!(GbsFile::parse(&data).is_err());
+    }
+
+    #[test]
+    fn builds_valid_cartridge() {
+        let gbs = GbsFile::parse(&gbs_data(0x100, 0)).unwrap();
+        let rom = gbs.build_rom(2).unwrap();
+
+        assert_eq!(rom.len(), 2 * ROM_BANK_LENGTH);
+        assert_eq!(rom[0x148], 0);
+        assert_eq!(&rom[0x104..0x134], &NINTENDO_LOGO[..]);
+        assert_eq!(&rom[0x134..0x139], b"TITLE");
+
+        let header_checksum = rom[0x134..0x14D].iter().fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1));
+        assert_eq!(rom[0x14D], header_checksum);
+
+        let global_checksum = rom.iter().enumerate()
+            .filter(|(index, _)| *index != 0x14E && *index != 0x14F)
+            .fold(0u16, |checksum, (_, byte)| checksum.wrapping_add(*byte as u16));
+        assert_eq!(&rom[0x14E..0x150], &global_checksum.to_be_bytes());
+
+        let load = LOAD_ADDRESS as usize;
+        assert_eq!(&rom[load..(load + 0x100)], &gbs.code[..]);
+    }
+
+    #[test]
+    fn driver_starts_track() {
+        let gbs = GbsFile::parse(&gbs_data(16, 0)).unwrap();
+        let rom = gbs.build_rom(3).unwrap();
+
+        assert_eq!(&rom[0x100..0x104], &[0x00, 0xC3, 0x50, 0x01]);
+        // RST $08 jumps to load address + $08, interrupts return.
+        assert_eq!(&rom[0x08..0x0B], &[0xC3, 0x08, 0x04]);
+        assert_eq!(rom[0x40], 0xD9);
+        assert_eq!(rom[0x50], 0xD9);
+
+        let driver = &rom[DRIVER_ADDRESS..];
+        assert!(find(driver, &[0x31, 0xFF, 0xDF]));
+        assert!(find(driver, &[0x3E, 0x02, 0xCD, 0x10, 0x04]));
+        assert!(find(driver, &[0x3E, INTERRUPT_VBLANK, 0xE0, 0xFF]));
+        assert!(find(driver, &[0x76, 0x00, 0xCD, 0x20, 0x04, 0x18, 0xF9]));
+    }
+
+    #[test]
+    fn timer_driven_rips_enable_timer_interrupt() {
+        let gbs = GbsFile::parse(&gbs_data(16, TAC_TIMER_ENABLE | 0x02)).unwrap();
+        let rom = gbs.build_rom(1).unwrap();
+
+        assert!(gbs.uses_timer());
+        let driver = &rom[DRIVER_ADDRESS..];
+        assert!(find(driver, &[0x3E, 0xC0, 0xE0, 0x06, 0x3E, 0x06, 0xE0, 0x07]));
+        assert!(find(driver, &[0x3E, INTERRUPT_TIMER, 0xE0, 0xFF]));
+    }
+
+    #[test]
+    fn large_rips_grow_the_rom() {
+        let gbs = GbsFile::parse(&gbs_data(0x8000, 0)).unwrap();
+        let rom = gbs.build_rom(1).unwrap();
+
+        assert_eq!(rom.len(), 4 * ROM_BANK_LENGTH);
+        assert_eq!(rom[0x148], 1);
+    }
+
+    #[test]
+    fn rejects_tracks_out_of_range() {
+        let gbs = GbsFile::parse(&gbs_data(16, 0)).unwrap();
+
+        assert!(gbs.build_rom(0).is_err());
+        assert!(gbs.build_rom(4).is_err());
+    }
+}
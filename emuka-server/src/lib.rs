@@ -8,5 +8,8 @@ extern crate log;
 
 pub mod emulators;
 pub mod game;
+pub mod gbs;
+pub mod states;
 pub mod audio;
+pub mod video;
 pub mod server;
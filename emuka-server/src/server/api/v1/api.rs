@@ -1,11 +1,11 @@
 use std::{collections::HashMap, convert::TryInto};
 
 use uuid::Uuid;
-use warp::Filter;
-use avro_rs::{Schema};
+use warp::{Filter, hyper::body::Bytes};
+use avro_rs::{Reader, Schema, types::Value};
 use lazy_static::lazy_static;
 
-use crate::{emulators::{EmulatorInternalCommand, EmulatorInternalCommandResults, EmulatorJoypadInput, ScreenData}, game::{GameFromFile, SaveFile}};
+use crate::{audio::{AudioFormat, SampleType, capture::CaptureSettings}, emulators::{rewind::{MAX_REWIND_CAPACITY, RewindAmount, RewindSettings}, EmulatorMemoryRegion, EmulatorResetKind, EmulatorInternalCommand, EmulatorInternalCommandResults, EmulatorJoypadInput, ScreenData}, game::{GameFromFile, SaveFile}, gbs::{GbsFile, GbsSettings}, states::data_path, video::{clip::ClipFormat, recording::{RecordingFormat, RecordingSettings}}};
 
 
 
@@ -23,12 +23,44 @@ impl TryInto<GameFromFile> for GameFromFileApi {
     }
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct GbsFromFileApi {
+    pub path: String,
+    /// Seconds before moving on to the next track, tracks loop forever if unset.
+    pub default_length: Option<f32>,
+    /// Per-track lengths in seconds, starting with track 1.
+    #[serde(default)]
+    pub lengths: Vec<f32>
+}
+
+impl TryInto<(GbsFile, GbsSettings)> for GbsFromFileApi {
+    type Error = eyre::Report;
+
+    fn try_into(self) -> Result<(GbsFile, GbsSettings), Self::Error> {
+        let settings = GbsSettings {
+            default_length: self.default_length,
+            lengths: self.lengths
+        };
+        Ok((GbsFile::load(&self.path)?, settings))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GbsTrackRequestApi {
+    pub track: u8
+}
+
 pub fn post_json <T: Send + Sync + serde::de::DeserializeOwned> () -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone {
     // When accepting a body, we want a JSON body
     // (and to reject huge payloads)...
     warp::body::content_length_limit(1024 * 16).and(warp::body::json())
 }
 
+pub fn post_bytes () -> impl Filter<Extract = (Bytes,), Error = warp::Rejection> + Clone {
+    // Binary bodies (save states, memory dumps) are much larger than JSON requests.
+    warp::body::content_length_limit(1024 * 1024).and(warp::body::bytes())
+}
+
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct SaveFromFileApi {
@@ -55,6 +87,34 @@ impl Into<(EmulatorJoypadInput, bool)> for EmulatorJoypadInputApi {
         (self.input, self.pressed)
     }
 }
+#[derive(Debug, Deserialize, Clone)]
+pub struct ResetRequestApi {
+    #[serde(default)]
+    pub kind: EmulatorResetKind
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StepRequestApi {
+    #[serde(default = "default_step_count")]
+    pub count: u32
+}
+
+fn default_step_count() -> u32 {
+    1
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RunUntilRequestApi {
+    pub pc: u16,
+    #[serde(default = "default_run_until_max_instructions")]
+    pub max_instructions: u32
+}
+
+fn default_run_until_max_instructions() -> u32 {
+    // About a second of emulation
+    1_048_576
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct RunStealthRequestApi {
     pub jump_location: u32,
@@ -106,6 +166,35 @@ pub struct ReadBulkSaveMemoryRequestApi {
     pub length: usize
 }
 
+#[derive(Debug, Deserialize, Copy, Clone)]
+pub enum MemoryDataFormat {
+    Raw,
+    Avro
+}
+
+impl Default for MemoryDataFormat {
+    fn default() -> Self {
+        Self::Raw
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReadBulkMemoryRequestApi {
+    pub region: EmulatorMemoryRegion,
+    pub offset: usize,
+    pub length: usize,
+    #[serde(default)]
+    pub format: MemoryDataFormat
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WriteBulkMemoryQueryApi {
+    pub region: EmulatorMemoryRegion,
+    pub offset: usize,
+    #[serde(default)]
+    pub format: MemoryDataFormat
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct WriteMemoryResponseApi {
     pub result: String
@@ -137,6 +226,59 @@ impl BurstResponseApi {
     }
 }
 
+fn default_screenshot_scale() -> u32 {
+    1
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScreenshotQueryApi {
+    #[serde(default = "default_screenshot_scale")]
+    pub scale: u32
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SaveScreenshotRequestApi {
+    /// Resolved against the `screenshots` data directory, which it can't
+    /// leave.
+    pub path: String,
+    #[serde(default = "default_screenshot_scale")]
+    pub scale: u32
+}
+
+fn default_clip_seconds() -> f32 {
+    5.0
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ClipQueryApi {
+    #[serde(default = "default_clip_seconds")]
+    pub seconds: f32,
+    #[serde(default)]
+    pub format: ClipFormat,
+    #[serde(default = "default_screenshot_scale")]
+    pub scale: u32
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StartRecordingRequestApi {
+    /// Output path without extension, resolved against the `recordings`
+    /// data directory, which it can't leave.
+    pub path: String,
+    #[serde(default)]
+    pub format: RecordingFormat
+}
+
+impl TryInto<RecordingSettings> for StartRecordingRequestApi {
+    type Error = eyre::Report;
+
+    fn try_into(self) -> Result<RecordingSettings, Self::Error> {
+        Ok(RecordingSettings {
+            path: data_path("recordings", &self.path)?,
+            format: self.format
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct ScreenDataApi {
     pub screen: Vec<u8>,
@@ -160,6 +302,26 @@ lazy_static! {
     pub static ref SCREEN_DATA_API_SCHEMA: Schema = Schema::parse_str(&RAW_SCHEMA_SCREEN_DATA_API).unwrap();
 }
 
+lazy_static! {
+    static ref RAW_SCHEMA_VIDEO_FRAME_API: &'static str = r#"
+        {
+            "type": "record",
+            "name": "VideoFrame",
+            "fields": [
+                {"name": "frame", "type": "long"},
+                {"name": "width", "type": "int"},
+                {"name": "height", "type": "int"},
+                {"name": "format", "type": "string"},
+                {"name": "base", "type": "long", "default": -1},
+                {"name": "palette", "type": "bytes"},
+                {"name": "data", "type": "bytes"}
+            ]
+        }
+    "#;
+
+    pub static ref VIDEO_FRAME_API_SCHEMA: Schema = Schema::parse_str(&RAW_SCHEMA_VIDEO_FRAME_API).unwrap();
+}
+
 impl From<Option<ScreenData>> for ScreenDataApi {
     fn from(data: Option<ScreenData>) -> Self {
         match data {
@@ -176,9 +338,74 @@ impl From<Option<ScreenData>> for ScreenDataApi {
         }
     }
 }
+#[derive(Debug, Deserialize, Clone)]
+pub struct StartAudioCaptureRequestApi {
+    /// Output path without extension, resolved against the `captures` data
+    /// directory, which it can't leave.
+    pub path: String,
+    /// Maximum size of a single WAV file, in bytes.
+    pub split_size: Option<u64>,
+    /// Seconds of silence after which the capture stops by itself.
+    pub until_silence: Option<f32>,
+    #[serde(default)]
+    pub silence_threshold: i16
+}
+
+impl TryInto<CaptureSettings> for StartAudioCaptureRequestApi {
+    type Error = eyre::Report;
+
+    fn try_into(self) -> Result<CaptureSettings, Self::Error> {
+        Ok(CaptureSettings {
+            path: data_path("captures", &self.path)?,
+            split_size: self.split_size,
+            until_silence: self.until_silence,
+            silence_threshold: self.silence_threshold
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StartStemsRecordingRequestApi {
+    /// Output path prefix, resolved against the `captures` data directory,
+    /// which it can't leave.
+    pub path: String
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StartVgmLogRequestApi {
+    /// Output path without extension, resolved against the `captures` data
+    /// directory, which it can't leave.
+    pub path: String
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AudioRegisterApi {
-    pub id: Uuid
+    pub id: Uuid,
+    #[serde(default)]
+    pub format: AudioFormat
+}
+
+/// Every field defaults to the emulator's native output.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AudioFormatQueryApi {
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub sample_type: Option<SampleType>
+}
+
+impl TryInto<AudioFormat> for AudioFormatQueryApi {
+    type Error = eyre::Report;
+
+    fn try_into(self) -> Result<AudioFormat, Self::Error> {
+        let default = AudioFormat::default();
+        let format = AudioFormat {
+            sample_rate: self.sample_rate.unwrap_or(default.sample_rate),
+            channels: self.channels.unwrap_or(default.channels),
+            sample_type: self.sample_type.unwrap_or(default.sample_type)
+        };
+        format.validate().map_err(eyre::Report::msg)?;
+        Ok(format)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -199,3 +426,174 @@ lazy_static! {
 
     pub static ref AUDIO_DATA_API_SCHEMA: Schema = Schema::parse_str(&RAW_SCHEMA_AUDIO_DATA_API).unwrap();
 }
+
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CreateStateSlotRequestApi {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub thumbnail: bool
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StateSlotRequestApi {
+    pub name: String
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RenameStateSlotRequestApi {
+    pub from: String,
+    pub to: String
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EnableRewindRequestApi {
+    #[serde(default = "default_rewind_interval")]
+    pub interval: u32,
+    #[serde(default = "default_rewind_capacity")]
+    pub capacity: usize
+}
+
+fn default_rewind_interval() -> u32 {
+    6
+}
+
+fn default_rewind_capacity() -> usize {
+    600
+}
+
+impl TryInto<RewindSettings> for EnableRewindRequestApi {
+    type Error = eyre::Report;
+
+    fn try_into(self) -> Result<RewindSettings, Self::Error> {
+        if self.capacity > MAX_REWIND_CAPACITY {
+            return Err(eyre::Report::msg(format!("Rewind capacity can't exceed {}", MAX_REWIND_CAPACITY)));
+        }
+
+        Ok(RewindSettings {
+            interval: self.interval,
+            capacity: self.capacity
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RewindRequestApi {
+    pub frames: Option<u32>,
+    pub seconds: Option<f32>,
+    #[serde(default)]
+    pub resume: bool
+}
+
+impl TryInto<RewindAmount> for RewindRequestApi {
+    type Error = eyre::Report;
+
+    fn try_into(self) -> Result<RewindAmount, Self::Error> {
+        match (self.frames, self.seconds) {
+            (Some(frames), None) => Ok(RewindAmount::Frames(frames)),
+            (None, Some(seconds)) => Ok(RewindAmount::Seconds(seconds)),
+            _ => Err(eyre::Report::msg("Expected exactly one of frames or seconds"))
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RewindResponseApi {
+    pub frames: u64
+}
+
+impl RewindResponseApi {
+    pub fn new(frames: u64) -> Self {
+        Self {
+            frames
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AddCheatRequestApi {
+    pub code: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CheatRequestApi {
+    pub code: String
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryDataApi {
+    pub data: Vec<u8>
+}
+
+lazy_static! {
+    static ref RAW_SCHEMA_MEMORY_DATA_API: &'static str = r#"
+        {
+            "type": "record",
+            "name": "MemoryData",
+            "fields": [
+                {"name": "data", "type": "bytes"}
+            ]
+        }
+    "#;
+
+    pub static ref MEMORY_DATA_API_SCHEMA: Schema = Schema::parse_str(&RAW_SCHEMA_MEMORY_DATA_API).unwrap();
+}
+
+impl MemoryDataApi {
+    pub fn from_avro(avro_data: &[u8]) -> Result<Self, eyre::Report> {
+        let reader = Reader::with_schema(&MEMORY_DATA_API_SCHEMA, avro_data)?;
+
+        for record in reader {
+            if let Value::Record(fields) = record? {
+                if let Some((_, Value::Bytes(data))) = fields.into_iter().find(|(name, _)| name == "data") {
+                    return Ok(Self { data });
+                }
+            }
+        }
+
+        Err(eyre::Report::msg("No data found"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StateDataApi {
+    pub state: Vec<u8>
+}
+
+lazy_static! {
+    static ref RAW_SCHEMA_STATE_DATA_API: &'static str = r#"
+        {
+            "type": "record",
+            "name": "StateData",
+            "fields": [
+                {"name": "state", "type": "bytes"}
+            ]
+        }
+    "#;
+
+    pub static ref STATE_DATA_API_SCHEMA: Schema = Schema::parse_str(&RAW_SCHEMA_STATE_DATA_API).unwrap();
+}
+
+impl StateDataApi {
+    pub fn from_avro(avro_data: &[u8]) -> Result<Self, eyre::Report> {
+        let reader = Reader::with_schema(&STATE_DATA_API_SCHEMA, avro_data)?;
+
+        for record in reader {
+            if let Value::Record(fields) = record? {
+                if let Some((_, Value::Bytes(state))) = fields.into_iter().find(|(name, _)| name == "state") {
+                    return Ok(Self { state });
+                }
+            }
+        }
+
+        Err(eyre::Report::msg("No state found"))
+    }
+}
@@ -1,15 +1,16 @@
 pub mod api;
-use crate::{audio::{AudioCommand, VecStereoWrapper}, emulators::{EmulatorInternalCommandResults, ScreenData}, server::api::v1::api::*};
+mod sockets;
+use crate::{audio::{self, AudioFormat, apu::ApuState, ApuChannel, AudioCommand, AudioQueue, AudioSource, capture::{CaptureSettings, CaptureStatus}, stems::StemsSummary, stream, vgm::VgmSummary}, emulators::{EmulatorInternalCommandResults, EmulatorMemoryRegion, ScreenData, cheats::{Cheat, CheatError}, debug::{Breakpoint, CpuState, CpuStateUpdate, DebugAddress, DebugPoints, StepResult, Watchpoint}, rewind::{RewindAmount, RewindSettings}}, gbs::{GbsFile, GbsSettings, GbsStatus, GbsTrackSelection}, server::api::v1::api::*, states::{SlotMetadata, data_path}, video::{clip::{self, ClipFormat}, recording::{RecordingSettings, RecordingSummary}, screenshot}};
 
-use std::{collections::{HashMap, VecDeque}, convert::TryInto};
+use std::{collections::HashMap, convert::TryInto};
 
 use color_eyre::Report;
 use tokio::sync::oneshot;
-use warp::{Filter, Reply, filters::BoxedFilter};
+use warp::{Filter, Reply, filters::BoxedFilter, hyper::body::Bytes};
 use uuid::Uuid;
 use avro_rs::{Codec, Writer, types::Record};
 
-use crate::{audio::{SAMPLE_RATE, StereoSample}, emulators::{EmulatorCommand, EmulatorJoypadInput}, game::{GameFromFile, SaveFile}};
+use crate::{emulators::{EmulatorCommand, EmulatorJoypadInput}, game::{GameFromFile, SaveFile}};
 use crate::audio::SAMPLES_MAP;
 
 
@@ -34,6 +35,57 @@ async fn load_game(
     reply
 }
 
+async fn load_gbs(
+    gbs_api: GbsFromFileApi,
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let result: Result<(GbsFile, GbsSettings), Report> = gbs_api.try_into();
+
+    match result {
+        Ok((file, settings)) => {
+            let (os_sender, os_receiver) = oneshot::channel::<Option<GbsStatus>>();
+            sender.send_command(EmulatorCommand::LoadGbs(Box::new(file), settings, os_sender));
+            gbs_status_reply(os_receiver.await.unwrap())
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+fn gbs_status_reply(status: Option<GbsStatus>) -> Result<warp::reply::Response, warp::Rejection> {
+    match status {
+        Some(status) => {
+            Ok(warp::reply::with_status(warp::reply::json(&status), warp::http::StatusCode::OK).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn select_gbs_track(
+    selection: GbsTrackSelection,
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<GbsStatus>>();
+    sender.send_command(EmulatorCommand::SelectGbsTrack(selection, os_sender));
+    gbs_status_reply(os_receiver.await.unwrap())
+}
+
+async fn get_gbs_status(
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<GbsStatus>>();
+    sender.send_command(EmulatorCommand::GetGbsStatus(os_sender));
+
+    match os_receiver.await.unwrap() {
+        Some(status) => Ok(warp::reply::json(&status).into_response()),
+        None => Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::NOT_FOUND).into_response())
+    }
+}
+
 async fn unload_game(
     emulator_sender: EmulatorCommandSender,
     audio_sender: AudioCommandSender,
@@ -71,6 +123,23 @@ async fn resume(
     Ok(warp::reply())
 }
 
+async fn pause(
+    emulator_sender: EmulatorCommandSender,
+    audio_sender: AudioCommandSender,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    audio_sender.send_command(AudioCommand::Pause);
+    emulator_sender.send_command(EmulatorCommand::Pause);
+    Ok(warp::reply())
+}
+
+async fn reset(
+    request: ResetRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    sender.send_command(EmulatorCommand::Reset(request.kind));
+    Ok(warp::reply())
+}
+
 async fn input(
     input_api: EmulatorJoypadInputApi,
     sender: EmulatorCommandSender
@@ -86,6 +155,11 @@ async fn get_screen_data(
     let (os_sender, os_receiver) = oneshot::channel::<Option<ScreenData>>();
     sender.send_command(EmulatorCommand::GetScreenData(os_sender));
     let value = os_receiver.await.unwrap();
+
+    Ok(warp::http::Response::new(encode_screen_data(value)))
+}
+
+fn encode_screen_data(value: Option<ScreenData>) -> Vec<u8> {
     let screen_data = ScreenDataApi::from(value);
     
     
@@ -97,9 +171,128 @@ async fn get_screen_data(
     record.put("height", screen_data.height as i32);
 
     writer.append(record).unwrap();
-    let data = writer.into_inner().unwrap();
+    writer.into_inner().unwrap()
+}
 
-    Ok(warp::http::Response::new(data))
+async fn get_screenshot(
+    query: ScreenshotQueryApi,
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<ScreenData>>();
+    sender.send_command(EmulatorCommand::GetScreenData(os_sender));
+
+    let screen_data = match os_receiver.await.unwrap() {
+        Some(screen_data) => screen_data,
+        None => {
+            return Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::NOT_FOUND).into_response());
+        }
+    };
+
+    match screenshot::encode_png(&screen_data, query.scale) {
+        Ok(png) => {
+            Ok(warp::reply::with_header(png, "Content-Type", "image/png").into_response())
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn save_screenshot(
+    request: SaveScreenshotRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<ScreenData>>();
+    sender.send_command(EmulatorCommand::GetScreenData(os_sender));
+
+    let screen_data = match os_receiver.await.unwrap() {
+        Some(screen_data) => screen_data,
+        None => {
+            return Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::NOT_FOUND));
+        }
+    };
+
+    let path = match data_path("screenshots", &request.path) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST));
+        }
+    };
+
+    match screenshot::save_png(&screen_data, request.scale, &path) {
+        Ok(_) => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+        }
+        Err(err) => {
+            eprintln!("{:?}: {}", path, err);
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
+        }
+    }
+}
+
+async fn get_clip(
+    query: ClipQueryApi
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let content_type = match query.format {
+        ClipFormat::Gif => "image/gif",
+        ClipFormat::Apng => "image/apng"
+    };
+
+    let result = tokio::task::spawn_blocking(move || clip::export(query.seconds, query.format, query.scale))
+        .await
+        .unwrap();
+
+    match result {
+        Ok(data) => {
+            Ok(warp::reply::with_header(data, "Content-Type", content_type).into_response())
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn start_recording(
+    request: StartRecordingRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let result: Result<RecordingSettings, Report> = request.try_into();
+
+    let settings = match result {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST));
+        }
+    };
+
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    sender.send_command(EmulatorCommand::StartRecording(settings, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
+    }
+}
+
+async fn stop_recording(
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<RecordingSummary>>();
+    sender.send_command(EmulatorCommand::StopRecording(os_sender));
+
+    match os_receiver.await.unwrap() {
+        Some(summary) => {
+            Ok(warp::reply::with_status(warp::reply::json(&summary), warp::http::StatusCode::OK).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
 }
 
 async fn save(
@@ -109,18 +302,472 @@ async fn save(
     Ok(warp::reply())
 }
 
-async fn register_audio_queue() -> Result<impl warp::Reply, warp::Rejection> {
+async fn save_state(
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<Vec<u8>>>();
+    sender.send_command(EmulatorCommand::SaveState(os_sender));
+    let value = os_receiver.await.unwrap();
+
+    match value {
+        Some(state) => {
+            let mut writer = Writer::with_codec(&api::STATE_DATA_API_SCHEMA, Vec::new(), Codec::Snappy);
+            let mut record = Record::new(writer.schema()).unwrap();
+
+            record.put("state", state);
+
+            writer.append(record).unwrap();
+            let data = writer.into_inner().unwrap();
+
+            Ok(warp::http::Response::new(data.into()))
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn load_state(
+    body: Bytes,
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let state = match StateDataApi::from_avro(&body) {
+        Ok(state_data) => state_data.state,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST));
+        }
+    };
+
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    sender.send_command(EmulatorCommand::LoadState(state, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
+    }
+}
+
+async fn list_state_slots(
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<Vec<SlotMetadata>>>();
+    sender.send_command(EmulatorCommand::ListStateSlots(os_sender));
+
+    match os_receiver.await.unwrap() {
+        Some(slots) => {
+            Ok(warp::reply::with_status(warp::reply::json(&slots), warp::http::StatusCode::OK).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn create_state_slot(
+    request: CreateStateSlotRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<SlotMetadata>>();
+    sender.send_command(EmulatorCommand::CreateStateSlot(request.name, request.thumbnail, os_sender));
+
+    match os_receiver.await.unwrap() {
+        Some(metadata) => {
+            Ok(warp::reply::with_status(warp::reply::json(&metadata), warp::http::StatusCode::OK).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn load_state_slot(
+    request: StateSlotRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    sender.send_command(EmulatorCommand::LoadStateSlot(request.name, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
+    }
+}
+
+async fn rename_state_slot(
+    request: RenameStateSlotRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<SlotMetadata>>();
+    sender.send_command(EmulatorCommand::RenameStateSlot(request.from, request.to, os_sender));
+
+    match os_receiver.await.unwrap() {
+        Some(metadata) => {
+            Ok(warp::reply::with_status(warp::reply::json(&metadata), warp::http::StatusCode::OK).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn delete_state_slot(
+    request: StateSlotRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    sender.send_command(EmulatorCommand::DeleteStateSlot(request.name, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
+    }
+}
+
+async fn get_state_slot_thumbnail(
+    name: String,
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<ScreenData>>();
+    sender.send_command(EmulatorCommand::GetStateSlotThumbnail(name, os_sender));
+    let value = os_receiver.await.unwrap();
+
+    Ok(warp::http::Response::new(encode_screen_data(value)))
+}
+
+async fn enable_rewind(
+    request: EnableRewindRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let result: Result<RewindSettings, Report> = request.try_into();
+
+    match result {
+        Ok(settings) => {
+            sender.send_command(EmulatorCommand::SetRewind(Some(settings)));
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
+        }
+    }
+}
+
+async fn disable_rewind(
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    sender.send_command(EmulatorCommand::SetRewind(None));
+    Ok(warp::reply())
+}
+
+async fn rewind(
+    request: RewindRequestApi,
+    emulator_sender: EmulatorCommandSender,
+    audio_sender: AudioCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let resume = request.resume;
+    let result: Result<RewindAmount, Report> = request.try_into();
+
+    let amount = match result {
+        Ok(amount) => amount,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response());
+        }
+    };
+
+    let (os_sender, os_receiver) = oneshot::channel::<Option<u64>>();
+    emulator_sender.send_command(EmulatorCommand::Rewind(amount, os_sender));
+
+    match os_receiver.await.unwrap() {
+        Some(frames) => {
+            if resume {
+                audio_sender.send_command(AudioCommand::Resume);
+                emulator_sender.send_command(EmulatorCommand::Resume);
+            }
+
+            let response = RewindResponseApi::new(frames);
+            Ok(warp::reply::with_status(warp::reply::json(&response), warp::http::StatusCode::OK).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+fn cheat_reply<T: serde::Serialize>(result: Result<T, CheatError>) -> warp::reply::Response {
+    match result {
+        Ok(value) => {
+            warp::reply::with_status(warp::reply::json(&value), warp::http::StatusCode::OK).into_response()
+        }
+        Err(err) => {
+            warp::reply::with_status(warp::reply::json(&err), warp::http::StatusCode::BAD_REQUEST).into_response()
+        }
+    }
+}
+
+async fn list_cheats(
+    only_enabled: bool,
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Result<Vec<Cheat>, CheatError>>();
+    sender.send_command(EmulatorCommand::ListCheats(os_sender));
+
+    let value = os_receiver.await.unwrap().map(|cheats| {
+        cheats.into_iter()
+            .filter(|cheat| cheat.enabled || !only_enabled)
+            .collect::<Vec<Cheat>>()
+    });
+
+    Ok(cheat_reply(value))
+}
+
+async fn add_cheat(
+    request: AddCheatRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Result<Cheat, CheatError>>();
+    sender.send_command(EmulatorCommand::AddCheat(request.code, request.description, request.enabled, os_sender));
+
+    Ok(cheat_reply(os_receiver.await.unwrap()))
+}
+
+async fn set_cheat_enabled(
+    enabled: bool,
+    request: CheatRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Result<Cheat, CheatError>>();
+    sender.send_command(EmulatorCommand::SetCheatEnabled(request.code, enabled, os_sender));
+
+    Ok(cheat_reply(os_receiver.await.unwrap()))
+}
+
+async fn remove_cheat(
+    request: CheatRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Result<(), CheatError>>();
+    sender.send_command(EmulatorCommand::RemoveCheat(request.code, os_sender));
+
+    Ok(cheat_reply(os_receiver.await.unwrap()))
+}
+
+fn register_audio_source(source: AudioSource, format: AudioFormatQueryApi) -> warp::reply::Response {
+    let format: AudioFormat = match format.try_into() {
+        Ok(format) => format,
+        Err(err) => {
+            eprintln!("{}", err);
+            return warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response();
+        }
+    };
+
     let id = Uuid::new_v4();
     
     {
         let mut lock = SAMPLES_MAP.lock().unwrap();
         let map = &mut *lock;
-        map.insert(id, VecDeque::with_capacity((SAMPLE_RATE * 20) as usize));
+        map.insert(id, AudioQueue::with_format(source, format));
+    }
+
+    warp::reply::json(&AudioRegisterApi {id, format}).into_response()
+}
+
+async fn register_audio_queue(
+    format: AudioFormatQueryApi
+) -> Result<warp::reply::Response, warp::Rejection> {
+    Ok(register_audio_source(AudioSource::Mix, format))
+}
+
+async fn unregister_audio_queue(
+    id: Uuid
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if audio::unregister(&id) {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::NOT_FOUND))
+    }
+}
+
+async fn list_audio_queues() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&audio::queue_stats()))
+}
+
+async fn get_audio_queue(
+    id: Uuid
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let stats = SAMPLES_MAP.lock().unwrap().get(&id).map(|queue| queue.stats(id));
+
+    match stats {
+        Some(stats) => Ok(warp::reply::json(&stats).into_response()),
+        None => Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::NOT_FOUND).into_response())
+    }
+}
+
+async fn stream_audio_queue(
+    id: Uuid
+) -> Result<warp::reply::Response, warp::Rejection> {
+    if !SAMPLES_MAP.lock().unwrap().contains_key(&id) {
+        return Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::NOT_FOUND).into_response());
+    }
+
+    let body = warp::hyper::Body::wrap_stream(stream::blocks(id));
+    Ok(warp::reply::with_header(warp::reply::Response::new(body), "content-type", "application/octet-stream").into_response())
+}
+
+async fn register_audio_channel_queue(
+    channel: ApuChannel,
+    format: AudioFormatQueryApi
+) -> Result<warp::reply::Response, warp::Rejection> {
+    Ok(register_audio_source(AudioSource::Channel(channel), format))
+}
+
+async fn start_stems_recording(
+    request: StartStemsRecordingRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let path = match data_path("captures", &request.path) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST));
+        }
+    };
+
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    sender.send_command(EmulatorCommand::StartStemsRecording(path, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
     }
+}
 
-    let audio_register = AudioRegisterApi {id};
+async fn stop_stems_recording(
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<Result<StemsSummary, Report>>>();
+    sender.send_command(EmulatorCommand::StopStemsRecording(os_sender));
 
-    Ok(warp::reply::json(&audio_register))
+    match os_receiver.await.unwrap() {
+        Some(Ok(summary)) => {
+            Ok(warp::reply::with_status(warp::reply::json(&summary), warp::http::StatusCode::OK).into_response())
+        }
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::INTERNAL_SERVER_ERROR).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn start_audio_capture(
+    request: StartAudioCaptureRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let result: Result<CaptureSettings, Report> = request.try_into();
+
+    let settings = match result {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST));
+        }
+    };
+
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    sender.send_command(EmulatorCommand::StartAudioCapture(settings, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
+    }
+}
+
+async fn stop_audio_capture(
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<Result<CaptureStatus, Report>>>();
+    sender.send_command(EmulatorCommand::StopAudioCapture(os_sender));
+
+    match os_receiver.await.unwrap() {
+        Some(Ok(status)) => {
+            Ok(warp::reply::with_status(warp::reply::json(&status), warp::http::StatusCode::OK).into_response())
+        }
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::INTERNAL_SERVER_ERROR).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn get_audio_capture_status(
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<CaptureStatus>>();
+    sender.send_command(EmulatorCommand::GetAudioCaptureStatus(os_sender));
+
+    Ok(warp::reply::json(&os_receiver.await.unwrap()))
+}
+
+async fn get_apu_state(
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<ApuState>>();
+    sender.send_command(EmulatorCommand::GetApuState(os_sender));
+
+    match os_receiver.await.unwrap() {
+        Some(state) => Ok(warp::reply::json(&state).into_response()),
+        None => Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::NOT_FOUND).into_response())
+    }
+}
+
+async fn start_vgm_log(
+    request: StartVgmLogRequestApi,
+    sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let path = match data_path("captures", &request.path) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST));
+        }
+    };
+
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    sender.send_command(EmulatorCommand::StartVgmLog(path, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
+    }
+}
+
+async fn stop_vgm_log(
+    sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<VgmSummary>>();
+    sender.send_command(EmulatorCommand::StopVgmLog(os_sender));
+
+    match os_receiver.await.unwrap() {
+        Some(summary) => {
+            Ok(warp::reply::with_status(warp::reply::json(&summary), warp::http::StatusCode::OK).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
 }
 
 async fn get_audio_samples (
@@ -130,24 +777,14 @@ async fn get_audio_samples (
         let mut lock = SAMPLES_MAP.lock().unwrap();
         let map = &mut *lock;
         let queue = map.get_mut(&request);
-        queue.map(|q| q.drain(..).collect::<Vec<StereoSample>>())
+        queue.map(|q| q.drain_encoded().2)
     };
 
     let mut writer = Writer::with_codec(&api::AUDIO_DATA_API_SCHEMA, Vec::new(), Codec::Snappy);
     let mut record = Record::new(writer.schema()).unwrap();
 
 
-    let bytes: Vec<u8> = match data {
-        Some(samples) => {
-            let wrapper = VecStereoWrapper {
-                inner: Some(samples)
-            };
-            wrapper.into()
-        }
-        None => {
-            Vec::new()
-        }
-    };
+    let bytes: Vec<u8> = data.unwrap_or_default();
     
     record.put("data", bytes);
 
@@ -177,20 +814,205 @@ async fn run_stealth (
     }
 }
 
-async fn read_memory (
-    request: ReadMemoryRequestApi,
+async fn read_memory (
+    request: ReadMemoryRequestApi,
+    emulator_sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<String>>();
+
+    emulator_sender.send_command(EmulatorCommand::ReadMemory(request.request, os_sender));
+
+    let value = os_receiver.await.unwrap();
+
+    match value {
+        Some(data) => {
+            let response = ReadMemoryResponseApi::new(data);
+            Ok(warp::reply::with_status(warp::reply::json(&response), warp::http::StatusCode::OK).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn read_bulk_save_memory(
+    request: ReadBulkSaveMemoryRequestApi,
+    emulator_sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<Vec<u8>>>();
+
+    emulator_sender.send_command(EmulatorCommand::ReadBulkMemory(EmulatorMemoryRegion::SaveRam, request.offset, request.length, os_sender));
+
+    let value = os_receiver.await.unwrap();
+
+    match value {
+        Some(data) => {
+            Ok(warp::reply::with_status(warp::reply::json(&data), warp::http::StatusCode::OK).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn read_bulk_memory(
+    request: ReadBulkMemoryRequestApi,
+    emulator_sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<Vec<u8>>>();
+
+    emulator_sender.send_command(EmulatorCommand::ReadBulkMemory(request.region, request.offset, request.length, os_sender));
+
+    let value = os_receiver.await.unwrap();
+
+    match (value, request.format) {
+        (Some(data), MemoryDataFormat::Raw) => {
+            Ok(warp::reply::with_header(data, "Content-Type", "application/octet-stream").into_response())
+        }
+        (Some(data), MemoryDataFormat::Avro) => {
+            let mut writer = Writer::with_codec(&api::MEMORY_DATA_API_SCHEMA, Vec::new(), Codec::Snappy);
+            let mut record = Record::new(writer.schema()).unwrap();
+
+            record.put("data", data);
+
+            writer.append(record).unwrap();
+            let data = writer.into_inner().unwrap();
+
+            Ok(warp::http::Response::new(data.into()))
+        }
+        (None, _) => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn write_bulk_memory(
+    query: WriteBulkMemoryQueryApi,
+    body: Bytes,
+    emulator_sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let data = match query.format {
+        MemoryDataFormat::Raw => Ok(body.to_vec()),
+        MemoryDataFormat::Avro => MemoryDataApi::from_avro(&body).map(|memory_data| memory_data.data)
+    };
+
+    let data = match data {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST));
+        }
+    };
+
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    emulator_sender.send_command(EmulatorCommand::WriteBulkMemory(query.region, query.offset, data, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
+    }
+}
+
+async fn write_memory (
+    request: WriteMemoryRequestApi,
+    emulator_sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<String>>();
+
+    emulator_sender.send_command(EmulatorCommand::WriteMemory(request.request, os_sender));
+
+    let value = os_receiver.await.unwrap();
+
+    match value {
+        Some(data) => {
+            let response = WriteMemoryResponseApi::new(data);
+            Ok(warp::reply::with_status(warp::reply::json(&response), warp::http::StatusCode::OK).into_response())
+        }
+        None => {
+            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+async fn set_breakpoint(
+    breakpoint: Breakpoint,
+    emulator_sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    emulator_sender.send_command(EmulatorCommand::SetBreakpoint(breakpoint, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
+    }
+}
+
+async fn clear_breakpoint(
+    location: DebugAddress,
+    emulator_sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    emulator_sender.send_command(EmulatorCommand::ClearBreakpoint(location, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::NOT_FOUND))
+    }
+}
+
+async fn set_watchpoint(
+    watchpoint: Watchpoint,
+    emulator_sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    emulator_sender.send_command(EmulatorCommand::SetWatchpoint(watchpoint, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST))
+    }
+}
+
+async fn clear_watchpoint(
+    location: DebugAddress,
+    emulator_sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<bool>();
+    emulator_sender.send_command(EmulatorCommand::ClearWatchpoint(location, os_sender));
+
+    if os_receiver.await.unwrap() {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::NOT_FOUND))
+    }
+}
+
+async fn clear_debug_points(
     emulator_sender: EmulatorCommandSender
-) -> Result<warp::reply::Response, warp::Rejection> {
-    let (os_sender, os_receiver) = oneshot::channel::<Option<String>>();
+) -> Result<impl warp::Reply, warp::Rejection> {
+    emulator_sender.send_command(EmulatorCommand::ClearDebugPoints);
+    Ok(warp::reply())
+}
 
-    emulator_sender.send_command(EmulatorCommand::ReadMemory(request.request, os_sender));
+async fn list_debug_points(
+    emulator_sender: EmulatorCommandSender
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<DebugPoints>();
+    emulator_sender.send_command(EmulatorCommand::ListDebugPoints(os_sender));
 
     let value = os_receiver.await.unwrap();
 
-    match value {
-        Some(data) => {
-            let response = ReadMemoryResponseApi::new(data);
-            Ok(warp::reply::with_status(warp::reply::json(&response), warp::http::StatusCode::OK).into_response())
+    Ok(warp::reply::json(&value))
+}
+
+async fn cpu_state_reply(os_receiver: oneshot::Receiver<Option<CpuState>>) -> Result<warp::reply::Response, warp::Rejection> {
+    match os_receiver.await.unwrap() {
+        Some(state) => {
+            Ok(warp::reply::with_status(warp::reply::json(&state), warp::http::StatusCode::OK).into_response())
         }
         None => {
             Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
@@ -198,19 +1020,29 @@ async fn read_memory (
     }
 }
 
-async fn read_bulk_save_memory(
-    request: ReadBulkSaveMemoryRequestApi,
+async fn get_cpu_state(
     emulator_sender: EmulatorCommandSender
 ) -> Result<warp::reply::Response, warp::Rejection> {
-    let (os_sender, os_receiver) = oneshot::channel::<Option<Vec<u8>>>();
+    let (os_sender, os_receiver) = oneshot::channel::<Option<CpuState>>();
+    emulator_sender.send_command(EmulatorCommand::GetCpuState(os_sender));
 
-    emulator_sender.send_command(EmulatorCommand::ReadBulkSaveMemory(request.offset, request.length, os_sender));
+    cpu_state_reply(os_receiver).await
+}
 
-    let value = os_receiver.await.unwrap();
+async fn set_cpu_state(
+    update: CpuStateUpdate,
+    emulator_sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<CpuState>>();
+    emulator_sender.send_command(EmulatorCommand::SetCpuState(update, os_sender));
 
-    match value {
-        Some(data) => {
-            Ok(warp::reply::with_status(warp::reply::json(&data), warp::http::StatusCode::OK).into_response())
+    cpu_state_reply(os_receiver).await
+}
+
+async fn step_reply(os_receiver: oneshot::Receiver<Option<StepResult>>) -> Result<warp::reply::Response, warp::Rejection> {
+    match os_receiver.await.unwrap() {
+        Some(result) => {
+            Ok(warp::reply::with_status(warp::reply::json(&result), warp::http::StatusCode::OK).into_response())
         }
         None => {
             Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
@@ -218,25 +1050,34 @@ async fn read_bulk_save_memory(
     }
 }
 
-async fn write_memory (
-    request: WriteMemoryRequestApi,
+async fn step_frames(
+    request: StepRequestApi,
     emulator_sender: EmulatorCommandSender
 ) -> Result<warp::reply::Response, warp::Rejection> {
-    let (os_sender, os_receiver) = oneshot::channel::<Option<String>>();
+    let (os_sender, os_receiver) = oneshot::channel::<Option<StepResult>>();
+    emulator_sender.send_command(EmulatorCommand::StepFrames(request.count, os_sender));
 
-    emulator_sender.send_command(EmulatorCommand::WriteMemory(request.request, os_sender));
+    step_reply(os_receiver).await
+}
 
-    let value = os_receiver.await.unwrap();
+async fn step_instructions(
+    request: StepRequestApi,
+    emulator_sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<StepResult>>();
+    emulator_sender.send_command(EmulatorCommand::StepInstructions(request.count, os_sender));
 
-    match value {
-        Some(data) => {
-            let response = WriteMemoryResponseApi::new(data);
-            Ok(warp::reply::with_status(warp::reply::json(&response), warp::http::StatusCode::OK).into_response())
-        }
-        None => {
-            Ok(warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST).into_response())
-        }
-    }
+    step_reply(os_receiver).await
+}
+
+async fn run_until(
+    request: RunUntilRequestApi,
+    emulator_sender: EmulatorCommandSender
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (os_sender, os_receiver) = oneshot::channel::<Option<StepResult>>();
+    emulator_sender.send_command(EmulatorCommand::RunUntil(request.pc, request.max_instructions, os_sender));
+
+    step_reply(os_receiver).await
 }
 
 async fn burst (
@@ -272,6 +1113,45 @@ pub fn routes(emulator_sender: EmulatorCommandSender, audio_sender: AudioCommand
         .and_then(unload_game);
     
 
+    let load_gbs_f = warp::post()
+        .and(warp::path("gbs"))
+        .and(warp::path("load"))
+        .and(warp::path::end())
+        .and(post_json::<GbsFromFileApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(load_gbs);
+
+    let select_gbs_track_f = warp::post()
+        .and(warp::path("gbs"))
+        .and(warp::path("track"))
+        .and(warp::path::end())
+        .and(post_json::<GbsTrackRequestApi>())
+        .map(|request: GbsTrackRequestApi| GbsTrackSelection::Track(request.track))
+        .and(emulator_command_filter.clone())
+        .and_then(select_gbs_track);
+
+    let next_gbs_track_f = warp::get()
+        .and(warp::path("gbs"))
+        .and(warp::path("next"))
+        .and(warp::path::end())
+        .map(|| GbsTrackSelection::Next)
+        .and(emulator_command_filter.clone())
+        .and_then(select_gbs_track);
+
+    let previous_gbs_track_f = warp::get()
+        .and(warp::path("gbs"))
+        .and(warp::path("previous"))
+        .and(warp::path::end())
+        .map(|| GbsTrackSelection::Previous)
+        .and(emulator_command_filter.clone())
+        .and_then(select_gbs_track);
+
+    let get_gbs_status_f = warp::get()
+        .and(warp::path("gbs"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(get_gbs_status);
+
     let load_save_f = warp::post()
         .and(warp::path("save"))
         .and(warp::path("load"))
@@ -287,6 +1167,20 @@ pub fn routes(emulator_sender: EmulatorCommandSender, audio_sender: AudioCommand
         .and(audio_command_filter.clone())
         .and_then(resume);
 
+    let pause_f = warp::get()
+        .and(warp::path("pause"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and(audio_command_filter.clone())
+        .and_then(pause);
+
+    let reset_f = warp::post()
+        .and(warp::path("reset"))
+        .and(warp::path::end())
+        .and(post_json::<ResetRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(reset);
+
     let input_f = warp::post()
         .and(warp::path("input"))
         .and(warp::path::end())
@@ -300,6 +1194,51 @@ pub fn routes(emulator_sender: EmulatorCommandSender, audio_sender: AudioCommand
         .and(emulator_command_filter.clone())
         .and_then(get_screen_data);
 
+    let get_screenshot_f = warp::get()
+        .and(warp::path("screen"))
+        .and(warp::path("png"))
+        .and(warp::path::end())
+        .and(warp::query::<ScreenshotQueryApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(get_screenshot);
+
+    let get_clip_f = warp::get()
+        .and(warp::path("screen"))
+        .and(warp::path("clip"))
+        .and(warp::path::end())
+        .and(warp::query::<ClipQueryApi>())
+        .and_then(get_clip);
+
+    let start_recording_f = warp::post()
+        .and(warp::path("record"))
+        .and(warp::path("start"))
+        .and(warp::path::end())
+        .and(post_json::<StartRecordingRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(start_recording);
+
+    let stop_recording_f = warp::get()
+        .and(warp::path("record"))
+        .and(warp::path("stop"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(stop_recording);
+
+    let video_stream_f = warp::path("screen")
+        .and(warp::path("stream"))
+        .and(warp::path::end())
+        .and(warp::ws())
+        .map(|ws: warp::ws::Ws| ws.on_upgrade(sockets::video_stream));
+
+    let save_screenshot_f = warp::post()
+        .and(warp::path("screen"))
+        .and(warp::path("png"))
+        .and(warp::path("save"))
+        .and(warp::path::end())
+        .and(post_json::<SaveScreenshotRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(save_screenshot);
+
     let save_f = warp::get()
         .and(warp::path("save"))
         .and(warp::path("save"))
@@ -307,12 +1246,201 @@ pub fn routes(emulator_sender: EmulatorCommandSender, audio_sender: AudioCommand
         .and(emulator_command_filter.clone())
         .and_then(save);
 
+    let save_state_f = warp::get()
+        .and(warp::path("state"))
+        .and(warp::path("save"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(save_state);
+
+    let load_state_f = warp::post()
+        .and(warp::path("state"))
+        .and(warp::path("load"))
+        .and(warp::path::end())
+        .and(post_bytes())
+        .and(emulator_command_filter.clone())
+        .and_then(load_state);
+
+    let list_state_slots_f = warp::get()
+        .and(warp::path("state"))
+        .and(warp::path("slots"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(list_state_slots);
+
+    let create_state_slot_f = warp::post()
+        .and(warp::path("state"))
+        .and(warp::path("slots"))
+        .and(warp::path("create"))
+        .and(warp::path::end())
+        .and(post_json::<CreateStateSlotRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(create_state_slot);
+
+    let load_state_slot_f = warp::post()
+        .and(warp::path("state"))
+        .and(warp::path("slots"))
+        .and(warp::path("load"))
+        .and(warp::path::end())
+        .and(post_json::<StateSlotRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(load_state_slot);
+
+    let rename_state_slot_f = warp::post()
+        .and(warp::path("state"))
+        .and(warp::path("slots"))
+        .and(warp::path("rename"))
+        .and(warp::path::end())
+        .and(post_json::<RenameStateSlotRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(rename_state_slot);
+
+    let delete_state_slot_f = warp::post()
+        .and(warp::path("state"))
+        .and(warp::path("slots"))
+        .and(warp::path("delete"))
+        .and(warp::path::end())
+        .and(post_json::<StateSlotRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(delete_state_slot);
+
+    let get_state_slot_thumbnail_f = warp::get()
+        .and(warp::path("state"))
+        .and(warp::path("slots"))
+        .and(warp::path("thumbnail"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(get_state_slot_thumbnail);
+
+    let enable_rewind_f = warp::post()
+        .and(warp::path("rewind"))
+        .and(warp::path("enable"))
+        .and(warp::path::end())
+        .and(post_json::<EnableRewindRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(enable_rewind);
+
+    let disable_rewind_f = warp::get()
+        .and(warp::path("rewind"))
+        .and(warp::path("disable"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(disable_rewind);
+
+    let rewind_f = warp::post()
+        .and(warp::path("rewind"))
+        .and(warp::path::end())
+        .and(post_json::<RewindRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and(audio_command_filter.clone())
+        .and_then(rewind);
+
+    let list_cheats_f = warp::get()
+        .and(warp::path("cheats"))
+        .and(warp::path::end())
+        .map(|| false)
+        .and(emulator_command_filter.clone())
+        .and_then(list_cheats);
+
+    let list_active_cheats_f = warp::get()
+        .and(warp::path("cheats"))
+        .and(warp::path("active"))
+        .and(warp::path::end())
+        .map(|| true)
+        .and(emulator_command_filter.clone())
+        .and_then(list_cheats);
+
+    let add_cheat_f = warp::post()
+        .and(warp::path("cheats"))
+        .and(warp::path("add"))
+        .and(warp::path::end())
+        .and(post_json::<AddCheatRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(add_cheat);
+
+    let enable_cheat_f = warp::post()
+        .and(warp::path("cheats"))
+        .and(warp::path("enable"))
+        .and(warp::path::end())
+        .map(|| true)
+        .and(post_json::<CheatRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(set_cheat_enabled);
+
+    let disable_cheat_f = warp::post()
+        .and(warp::path("cheats"))
+        .and(warp::path("disable"))
+        .and(warp::path::end())
+        .map(|| false)
+        .and(post_json::<CheatRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(set_cheat_enabled);
+
+    let remove_cheat_f = warp::post()
+        .and(warp::path("cheats"))
+        .and(warp::path("remove"))
+        .and(warp::path::end())
+        .and(post_json::<CheatRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(remove_cheat);
+
     let register_audio_queue_f = warp::get()
         .and(warp::path("audio"))
         .and(warp::path("register"))
         .and(warp::path::end())
+        .and(warp::query::<AudioFormatQueryApi>())
         .and_then(register_audio_queue);
 
+    let register_audio_channel_queue_f = warp::get()
+        .and(warp::path("audio"))
+        .and(warp::path("register"))
+        .and(warp::path::param::<ApuChannel>())
+        .and(warp::path::end())
+        .and(warp::query::<AudioFormatQueryApi>())
+        .and_then(register_audio_channel_queue);
+
+    let start_stems_recording_f = warp::post()
+        .and(warp::path("audio"))
+        .and(warp::path("stems"))
+        .and(warp::path("start"))
+        .and(warp::path::end())
+        .and(post_json::<StartStemsRecordingRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(start_stems_recording);
+
+    let stop_stems_recording_f = warp::get()
+        .and(warp::path("audio"))
+        .and(warp::path("stems"))
+        .and(warp::path("stop"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(stop_stems_recording);
+
+    let get_apu_state_f = warp::get()
+        .and(warp::path("audio"))
+        .and(warp::path("apu"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(get_apu_state);
+
+    let start_vgm_log_f = warp::post()
+        .and(warp::path("audio"))
+        .and(warp::path("vgm"))
+        .and(warp::path("start"))
+        .and(warp::path::end())
+        .and(post_json::<StartVgmLogRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(start_vgm_log);
+
+    let stop_vgm_log_f = warp::get()
+        .and(warp::path("audio"))
+        .and(warp::path("vgm"))
+        .and(warp::path("stop"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(stop_vgm_log);
+
     let get_audio_samples_f = warp::get()
         .and(warp::path("audio"))
         .and(warp::path("get"))
@@ -320,6 +1448,57 @@ pub fn routes(emulator_sender: EmulatorCommandSender, audio_sender: AudioCommand
         .and(warp::path::end())
         .and_then(get_audio_samples);
 
+    let unregister_audio_queue_f = warp::get()
+        .and(warp::path("audio"))
+        .and(warp::path("unregister"))
+        .and(warp::path::param().map(|id: Uuid| id))
+        .and(warp::path::end())
+        .and_then(unregister_audio_queue);
+
+    let list_audio_queues_f = warp::get()
+        .and(warp::path("audio"))
+        .and(warp::path("queues"))
+        .and(warp::path::end())
+        .and_then(list_audio_queues);
+
+    let get_audio_queue_f = warp::get()
+        .and(warp::path("audio"))
+        .and(warp::path("queues"))
+        .and(warp::path::param().map(|id: Uuid| id))
+        .and(warp::path::end())
+        .and_then(get_audio_queue);
+
+    let stream_audio_queue_f = warp::get()
+        .and(warp::path("audio"))
+        .and(warp::path("stream"))
+        .and(warp::path::param().map(|id: Uuid| id))
+        .and(warp::path::end())
+        .and_then(stream_audio_queue);
+
+    let start_audio_capture_f = warp::post()
+        .and(warp::path("audio"))
+        .and(warp::path("capture"))
+        .and(warp::path("start"))
+        .and(warp::path::end())
+        .and(post_json::<StartAudioCaptureRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(start_audio_capture);
+
+    let stop_audio_capture_f = warp::get()
+        .and(warp::path("audio"))
+        .and(warp::path("capture"))
+        .and(warp::path("stop"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(stop_audio_capture);
+
+    let get_audio_capture_status_f = warp::get()
+        .and(warp::path("audio"))
+        .and(warp::path("capture"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(get_audio_capture_status);
+
     let run_stealth_f = warp::post()
         .and(warp::path("internal"))
         .and(warp::path("run_stealth"))
@@ -344,6 +1523,22 @@ pub fn routes(emulator_sender: EmulatorCommandSender, audio_sender: AudioCommand
         .and(emulator_command_filter.clone())
         .and_then(read_bulk_save_memory);
 
+    let read_bulk_memory_f = warp::post()
+        .and(warp::path("internal"))
+        .and(warp::path("read_bulk_memory"))
+        .and(warp::path::end())
+        .and(post_json::<ReadBulkMemoryRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(read_bulk_memory);
+
+    let write_bulk_memory_f = warp::post()
+        .and(warp::path("internal"))
+        .and(warp::path("write_bulk_memory"))
+        .and(warp::path::end())
+        .and(warp::query::<WriteBulkMemoryQueryApi>())
+        .and(post_bytes())
+        .and(emulator_command_filter.clone())
+        .and_then(write_bulk_memory);
 
     let write_memory_f = warp::post()
         .and(warp::path("internal"))
@@ -353,6 +1548,111 @@ pub fn routes(emulator_sender: EmulatorCommandSender, audio_sender: AudioCommand
         .and(emulator_command_filter.clone())
         .and_then(write_memory);
 
+    let watch_f = warp::path("watch")
+        .and(warp::path::end())
+        .and(warp::ws())
+        .and(emulator_command_filter.clone())
+        .map(|ws: warp::ws::Ws, emulator_sender: EmulatorCommandSender| {
+            ws.on_upgrade(move |socket| sockets::watch(socket, emulator_sender))
+        });
+
+    let set_breakpoint_f = warp::post()
+        .and(warp::path("debug"))
+        .and(warp::path("breakpoints"))
+        .and(warp::path("set"))
+        .and(warp::path::end())
+        .and(post_json::<Breakpoint>())
+        .and(emulator_command_filter.clone())
+        .and_then(set_breakpoint);
+
+    let clear_breakpoint_f = warp::post()
+        .and(warp::path("debug"))
+        .and(warp::path("breakpoints"))
+        .and(warp::path("clear"))
+        .and(warp::path::end())
+        .and(post_json::<DebugAddress>())
+        .and(emulator_command_filter.clone())
+        .and_then(clear_breakpoint);
+
+    let set_watchpoint_f = warp::post()
+        .and(warp::path("debug"))
+        .and(warp::path("watchpoints"))
+        .and(warp::path("set"))
+        .and(warp::path::end())
+        .and(post_json::<Watchpoint>())
+        .and(emulator_command_filter.clone())
+        .and_then(set_watchpoint);
+
+    let clear_watchpoint_f = warp::post()
+        .and(warp::path("debug"))
+        .and(warp::path("watchpoints"))
+        .and(warp::path("clear"))
+        .and(warp::path::end())
+        .and(post_json::<DebugAddress>())
+        .and(emulator_command_filter.clone())
+        .and_then(clear_watchpoint);
+
+    let clear_debug_points_f = warp::get()
+        .and(warp::path("debug"))
+        .and(warp::path("clear"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(clear_debug_points);
+
+    let list_debug_points_f = warp::get()
+        .and(warp::path("debug"))
+        .and(warp::path("points"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(list_debug_points);
+
+    let debug_events_f = warp::path("debug")
+        .and(warp::path("events"))
+        .and(warp::path::end())
+        .and(warp::ws())
+        .map(|ws: warp::ws::Ws| ws.on_upgrade(sockets::debug_events));
+
+    let get_cpu_state_f = warp::get()
+        .and(warp::path("debug"))
+        .and(warp::path("registers"))
+        .and(warp::path::end())
+        .and(emulator_command_filter.clone())
+        .and_then(get_cpu_state);
+
+    let set_cpu_state_f = warp::post()
+        .and(warp::path("debug"))
+        .and(warp::path("registers"))
+        .and(warp::path::end())
+        .and(post_json::<CpuStateUpdate>())
+        .and(emulator_command_filter.clone())
+        .and_then(set_cpu_state);
+
+    let step_frames_f = warp::post()
+        .and(warp::path("debug"))
+        .and(warp::path("step"))
+        .and(warp::path("frames"))
+        .and(warp::path::end())
+        .and(post_json::<StepRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(step_frames);
+
+    let step_instructions_f = warp::post()
+        .and(warp::path("debug"))
+        .and(warp::path("step"))
+        .and(warp::path("instructions"))
+        .and(warp::path::end())
+        .and(post_json::<StepRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(step_instructions);
+
+    let run_until_f = warp::post()
+        .and(warp::path("debug"))
+        .and(warp::path("run_until"))
+        .and(warp::path::end())
+        .and(post_json::<RunUntilRequestApi>())
+        .and(emulator_command_filter.clone())
+        .and_then(run_until);
+
     let burst_f = warp::post()
         .and(warp::path("internal"))
         .and(warp::path("burst"))
@@ -362,27 +1662,103 @@ pub fn routes(emulator_sender: EmulatorCommandSender, audio_sender: AudioCommand
         .and_then(burst);
     
 
+    // Grouped and boxed separately to keep the combined filter type shallow.
+    let screen_f = get_screen_data_f
+        .or(get_screenshot_f)
+        .or(save_screenshot_f)
+        .or(video_stream_f)
+        .or(get_clip_f)
+        .or(start_recording_f)
+        .or(stop_recording_f)
+        .boxed();
+
+    let audio_f = register_audio_queue_f
+        .or(register_audio_channel_queue_f)
+        .or(get_audio_samples_f)
+        .or(stream_audio_queue_f)
+        .or(unregister_audio_queue_f)
+        .or(list_audio_queues_f)
+        .or(get_audio_queue_f)
+        .or(start_audio_capture_f)
+        .or(stop_audio_capture_f)
+        .or(get_audio_capture_status_f)
+        .or(start_stems_recording_f)
+        .or(stop_stems_recording_f)
+        .or(start_vgm_log_f)
+        .or(stop_vgm_log_f)
+        .or(get_apu_state_f)
+        .boxed();
+
+    let gbs_f = load_gbs_f
+        .or(select_gbs_track_f)
+        .or(next_gbs_track_f)
+        .or(previous_gbs_track_f)
+        .or(get_gbs_status_f)
+        .boxed();
+
+    let memory_f = run_stealth_f
+        .or(read_memory_f)
+        .or(read_bulk_save_memory_f)
+        .or(read_bulk_memory_f)
+        .or(write_bulk_memory_f)
+        .or(write_memory_f)
+        .or(watch_f)
+        .boxed();
+
+    let debug_f = set_breakpoint_f
+        .or(clear_breakpoint_f)
+        .or(set_watchpoint_f)
+        .or(clear_watchpoint_f)
+        .or(clear_debug_points_f)
+        .or(list_debug_points_f)
+        .or(debug_events_f)
+        .or(get_cpu_state_f)
+        .or(set_cpu_state_f)
+        .or(step_frames_f)
+        .or(step_instructions_f)
+        .or(run_until_f)
+        .boxed();
+
     load_game_f
     .or(unload_game_f)
+    .or(gbs_f)
 
     .or(load_save_f)
     .or(save_f)
 
+    .or(save_state_f)
+    .or(load_state_f)
+    .or(list_state_slots_f)
+    .or(create_state_slot_f)
+    .or(load_state_slot_f)
+    .or(rename_state_slot_f)
+    .or(delete_state_slot_f)
+    .or(get_state_slot_thumbnail_f)
+
+    .or(enable_rewind_f)
+    .or(disable_rewind_f)
+    .or(rewind_f)
+
+    .or(list_cheats_f)
+    .or(list_active_cheats_f)
+    .or(add_cheat_f)
+    .or(enable_cheat_f)
+    .or(disable_cheat_f)
+    .or(remove_cheat_f)
+
     .or(resume_f)
+    .or(pause_f)
+    .or(reset_f)
     
     .or(input_f)
     
-    .or(get_screen_data_f)
+    .or(screen_f)
 
-    .or(register_audio_queue_f)
-    .or(get_audio_samples_f)
+    .or(audio_f)
     
-    .or(run_stealth_f)
+    .or(memory_f)
 
-    .or(read_memory_f)
-    .or(read_bulk_save_memory_f)
-    
-    .or(write_memory_f)
+    .or(debug_f)
     
     .or(burst_f)
     
@@ -0,0 +1,149 @@
+use avro_rs::{Codec, Writer, types::Record};
+use futures::{SinkExt, StreamExt};
+use tokio::sync::{broadcast::error::RecvError, mpsc::unbounded_channel};
+use uuid::Uuid;
+use warp::ws::{Message, WebSocket};
+
+use crate::{emulators::{EmulatorCommand, debug::DEBUG_EVENTS, watch::{WatchRequest, WatchUpdate}}, server::api::EmulatorCommandSender, video::{delta, stream::{self, FrameEncoding, VIDEO_FRAMES, VideoCompression, VideoFrame, VideoStreamMessage, VideoStreamRequest, VideoStreamSubscription}}};
+
+use super::api::VIDEO_FRAME_API_SCHEMA;
+
+pub async fn watch(socket: WebSocket, emulator_sender: EmulatorCommandSender) {
+    let (mut socket_sender, mut socket_receiver) = socket.split();
+    let (update_sender, mut update_receiver) = unbounded_channel::<WatchUpdate>();
+    let id = Uuid::new_v4();
+
+    loop {
+        tokio::select! {
+            message = socket_receiver.next() => match message {
+                Some(Ok(message)) => {
+                    if message.is_close() {
+                        break;
+                    }
+
+                    if let Ok(text) = message.to_str() {
+                        match serde_json::from_str::<WatchRequest>(text) {
+                            Ok(request) => emulator_sender.send_command(EmulatorCommand::Watch(id, request, update_sender.clone())),
+                            Err(err) => eprintln!("{}", err)
+                        }
+                    }
+                },
+                _ => break
+            },
+            update = update_receiver.recv() => match update {
+                Some(update) => {
+                    let text = serde_json::to_string(&update).unwrap();
+                    if socket_sender.send(Message::text(text)).await.is_err() {
+                        break;
+                    }
+                },
+                None => break
+            }
+        }
+    }
+
+    emulator_sender.send_command(EmulatorCommand::Unwatch(id));
+}
+
+pub async fn debug_events(socket: WebSocket) {
+    let (mut socket_sender, mut socket_receiver) = socket.split();
+    let mut events = DEBUG_EVENTS.subscribe();
+
+    loop {
+        tokio::select! {
+            message = socket_receiver.next() => match message {
+                Some(Ok(message)) if !message.is_close() => continue,
+                _ => break
+            },
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let text = serde_json::to_string(&event).unwrap();
+                    if socket_sender.send(Message::text(text)).await.is_err() {
+                        break;
+                    }
+                },
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break
+            }
+        }
+    }
+}
+
+/// Keyframes carry every pixel and a `base` of -1. Delta frames carry the
+/// changed tiles, as packed by `delta::extract_tiles`, relative to `base`.
+fn encode_video_frame(frame: &VideoFrame, request: &VideoStreamRequest, encoding: FrameEncoding) -> Vec<u8> {
+    let converted = stream::convert(frame, request.format);
+    let (base, data) = match encoding {
+        FrameEncoding::Key => (-1, converted.data),
+        FrameEncoding::Delta { base, tiles } => {
+            let bytes_per_pixel = converted.format.bytes_per_pixel();
+            (base as i64, delta::extract_tiles(&converted.data, bytes_per_pixel, frame.width, frame.height, &tiles))
+        }
+    };
+
+    let codec = match request.compression {
+        VideoCompression::None => Codec::Null,
+        VideoCompression::Snappy => Codec::Snappy,
+        VideoCompression::Deflate => Codec::Deflate
+    };
+
+    let mut writer = Writer::with_codec(&VIDEO_FRAME_API_SCHEMA, Vec::new(), codec);
+    let mut record = Record::new(writer.schema()).unwrap();
+
+    record.put("frame", frame.number as i64);
+    record.put("width", frame.width as i32);
+    record.put("height", frame.height as i32);
+    record.put("format", format!("{:?}", converted.format));
+    record.put("base", base);
+    record.put("palette", converted.palette);
+    record.put("data", data);
+
+    writer.append(record).unwrap();
+    writer.into_inner().unwrap()
+}
+
+/// Sends every new frame as an Avro `VideoFrame`. Clients can send a JSON
+/// `VideoStreamRequest` at any time to change the divisor, format, compression
+/// or delta mode, and `{"ack": frame}` to acknowledge decoded frames.
+pub async fn video_stream(socket: WebSocket) {
+    let (mut socket_sender, mut socket_receiver) = socket.split();
+    let mut frames = VIDEO_FRAMES.subscribe();
+    let mut subscription = VideoStreamSubscription::new(VideoStreamRequest::default());
+
+    loop {
+        tokio::select! {
+            message = socket_receiver.next() => match message {
+                Some(Ok(message)) => {
+                    if message.is_close() {
+                        break;
+                    }
+
+                    if let Ok(text) = message.to_str() {
+                        match serde_json::from_str::<VideoStreamMessage>(text) {
+                            Ok(VideoStreamMessage::Ack { ack }) => subscription.acknowledge(ack),
+                            Ok(VideoStreamMessage::Configure(request)) => subscription.configure(request),
+                            Err(err) => eprintln!("{}", err)
+                        }
+                    }
+                },
+                _ => break
+            },
+            frame = frames.recv() => match frame {
+                Ok(frame) => {
+                    if !subscription.accept(&frame) {
+                        continue;
+                    }
+
+                    let encoding = subscription.encoding(&frame);
+                    let data = encode_video_frame(&frame, &subscription.request, encoding);
+                    if socket_sender.send(Message::binary(data)).await.is_err() {
+                        break;
+                    }
+                },
+                // Slow clients simply miss frames.
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break
+            }
+        }
+    }
+}
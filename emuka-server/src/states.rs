@@ -0,0 +1,352 @@
+use std::{fs, path::{Component, Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+use eyre::{Report, Result};
+
+use crate::{emulators::ScreenData, game::Game};
+
+static DATA_DIRECTORY_VARIABLE: &str = "EMUKA_DATA_DIR";
+static DEFAULT_DATA_DIRECTORY: &str = "./data";
+
+/// Base directory for everything Emuka persists per ROM.
+/// Can be overridden with the `EMUKA_DATA_DIR` environment variable.
+pub fn data_directory() -> PathBuf {
+    match std::env::var(DATA_DIRECTORY_VARIABLE) {
+        Ok(directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ => PathBuf::from(DEFAULT_DATA_DIRECTORY)
+    }
+}
+
+/// Resolves a client supplied `path` inside the `directory` subdirectory of
+/// the data directory. Absolute paths and `..` components are rejected, so
+/// nothing can end up outside of it.
+pub fn data_path(directory: &str, path: &str) -> Result<PathBuf> {
+    let relative = Path::new(path);
+    let contained = relative.components().all(|component| match component {
+        Component::Normal(_) | Component::CurDir => true,
+        _ => false
+    });
+
+    if path.is_empty() || !contained {
+        return Err(Report::msg(format!("Invalid path: {:?}", path)));
+    }
+
+    Ok(data_directory().join(directory).join(relative))
+}
+
+pub fn rom_hash(data: &[u8]) -> String {
+    format!("{:08X}", crc32fast::hash(data))
+}
+
+pub fn rom_directory(hash: &str) -> PathBuf {
+    data_directory().join(hash)
+}
+
+/// Writes `data` to a temporary file next to `path` before moving it into
+/// place, so `path` never holds a partially written file.
+fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    if let Err(err) = fs::write(&temporary, data).and_then(|_| fs::rename(&temporary, path)) {
+        let _ = fs::remove_file(&temporary);
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ThumbnailInfo {
+    pub width: u32,
+    pub height: u32
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SlotMetadata {
+    pub name: String,
+    pub rom_name: String,
+    pub rom_hash: String,
+    pub timestamp: u64,
+    pub frame_count: u64,
+    pub thumbnail: Option<ThumbnailInfo>
+}
+
+#[derive(Debug)]
+pub struct SlotManager {
+    directory: PathBuf,
+    rom_name: String,
+    rom_hash: String
+}
+
+impl SlotManager {
+    pub fn new(game: &dyn Game) -> Self {
+        let rom_hash = rom_hash(game.data());
+
+        Self {
+            directory: rom_directory(&rom_hash).join("states"),
+            rom_name: game.name().to_owned(),
+            rom_hash
+        }
+    }
+
+    pub fn rom_hash(&self) -> &str {
+        &self.rom_hash
+    }
+
+    fn validate_name(name: &str) -> Result<()> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' || c == ' ');
+
+        if valid {
+            Ok(())
+        } else {
+            Err(Report::msg(format!("Invalid slot name: {:?}", name)))
+        }
+    }
+
+    fn slot_path(&self, name: &str, extension: &str) -> PathBuf {
+        self.directory.join(format!("{}.{}", name, extension))
+    }
+
+    fn read_metadata(path: &Path) -> Result<SlotMetadata> {
+        let data = fs::read(path)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    fn write_metadata(&self, metadata: &SlotMetadata) -> Result<()> {
+        let data = serde_json::to_vec_pretty(metadata)?;
+        write_file(&self.slot_path(&metadata.name, "json"), &data)
+    }
+
+    pub fn list(&self) -> Result<Vec<SlotMetadata>> {
+        if !self.directory.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut slots = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |extension| extension == "json") {
+                match Self::read_metadata(&path) {
+                    Ok(metadata) => slots.push(metadata),
+                    Err(err) => eprintln!("{:?}: {}", path, err)
+                }
+            }
+        }
+
+        slots.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.name.cmp(&b.name)));
+        Ok(slots)
+    }
+
+    pub fn create(&self, name: &str, state: &[u8], frame_count: u64, thumbnail: Option<&ScreenData>) -> Result<SlotMetadata> {
+        Self::validate_name(name)?;
+        fs::create_dir_all(&self.directory)?;
+
+        // The metadata goes last, so an interrupted write never lists a slot
+        // whose files are missing or incomplete.
+        let thumbnail_path = self.slot_path(name, "rgba");
+        let thumbnail = match thumbnail {
+            Some(screen_data) => {
+                write_file(&thumbnail_path, &screen_data.data)?;
+                Some(ThumbnailInfo {
+                    width: screen_data.width,
+                    height: screen_data.height
+                })
+            },
+            None => None
+        };
+
+        write_file(&self.slot_path(name, "state"), state)?;
+
+        let metadata = SlotMetadata {
+            name: name.to_owned(),
+            rom_name: self.rom_name.clone(),
+            rom_hash: self.rom_hash.clone(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            frame_count,
+            thumbnail
+        };
+
+        self.write_metadata(&metadata)?;
+
+        // Only dropped once the metadata no longer refers to it.
+        if metadata.thumbnail.is_none() && thumbnail_path.exists() {
+            fs::remove_file(&thumbnail_path)?;
+        }
+
+        Ok(metadata)
+    }
+
+    pub fn load(&self, name: &str) -> Result<(SlotMetadata, Vec<u8>)> {
+        Self::validate_name(name)?;
+
+        let metadata = Self::read_metadata(&self.slot_path(name, "json"))?;
+        let state = fs::read(self.slot_path(name, "state"))?;
+
+        Ok((metadata, state))
+    }
+
+    pub fn thumbnail(&self, name: &str) -> Result<Option<ScreenData>> {
+        Self::validate_name(name)?;
+
+        let metadata = Self::read_metadata(&self.slot_path(name, "json"))?;
+        match metadata.thumbnail {
+            Some(info) => Ok(Some(ScreenData {
+                data: fs::read(self.slot_path(name, "rgba"))?,
+                width: info.width,
+                height: info.height
+            })),
+            None => Ok(None)
+        }
+    }
+
+    pub fn rename(&self, from: &str, to: &str) -> Result<SlotMetadata> {
+        Self::validate_name(from)?;
+        Self::validate_name(to)?;
+
+        if self.slot_path(to, "json").exists() {
+            return Err(Report::msg(format!("Slot {:?} already exists", to)));
+        }
+
+        let mut metadata = Self::read_metadata(&self.slot_path(from, "json"))?;
+        let extensions: &[&str] = if metadata.thumbnail.is_some() { &["state", "rgba"] } else { &["state"] };
+        metadata.name = to.to_owned();
+
+        let mut moved = Vec::new();
+        let result = extensions.iter()
+            .try_for_each(|extension| -> Result<()> {
+                fs::rename(self.slot_path(from, extension), self.slot_path(to, extension))?;
+                moved.push(*extension);
+                Ok(())
+            })
+            .and_then(|_| self.write_metadata(&metadata))
+            .and_then(|_| Ok(fs::remove_file(self.slot_path(from, "json"))?));
+
+        // Whatever was moved goes back, so a failed rename leaves `from` as it was.
+        if let Err(err) = result {
+            let _ = fs::remove_file(self.slot_path(to, "json"));
+            for extension in moved.iter().rev() {
+                let _ = fs::rename(self.slot_path(to, extension), self.slot_path(from, extension));
+            }
+            return Err(err);
+        }
+
+        Ok(metadata)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        Self::validate_name(name)?;
+
+        let metadata_path = self.slot_path(name, "json");
+        if !metadata_path.exists() {
+            return Err(Report::msg(format!("Slot {:?} does not exist", name)));
+        }
+
+        for extension in &["state", "rgba"] {
+            let path = self.slot_path(name, extension);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        fs::remove_file(metadata_path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(test: &str) -> SlotManager {
+        let directory = std::env::temp_dir().join(format!("emuka-states-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        SlotManager {
+            directory,
+            rom_name: "Test".to_owned(),
+            rom_hash: "00000000".to_owned()
+        }
+    }
+
+    fn files(manager: &SlotManager) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(&manager.directory).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn screen() -> ScreenData {
+        ScreenData {
+            data: vec![0xFF; 4],
+            width: 1,
+            height: 1
+        }
+    }
+
+    #[test]
+    fn create_overwrites_without_leftovers() {
+        let manager = manager("create");
+
+        manager.create("slot", &[1, 2, 3], 10, Some(&screen())).unwrap();
+        assert_eq!(files(&manager), vec!["slot.json", "slot.rgba", "slot.state"]);
+
+        let metadata = manager.create("slot", &[4, 5], 20, None).unwrap();
+        assert!(metadata.thumbnail.is_none());
+        assert_eq!(files(&manager), vec!["slot.json", "slot.state"]);
+
+        let (metadata, state) = manager.load("slot").unwrap();
+        assert_eq!(metadata.frame_count, 20);
+        assert_eq!(state, vec![4, 5]);
+
+        fs::remove_dir_all(&manager.directory).unwrap();
+    }
+
+    #[test]
+    fn rename_moves_every_file() {
+        let manager = manager("rename");
+
+        manager.create("before", &[1, 2, 3], 10, Some(&screen())).unwrap();
+        let metadata = manager.rename("before", "after").unwrap();
+        assert_eq!(metadata.name, "after");
+        assert_eq!(files(&manager), vec!["after.json", "after.rgba", "after.state"]);
+
+        let (metadata, state) = manager.load("after").unwrap();
+        assert_eq!(metadata.name, "after");
+        assert_eq!(state, vec![1, 2, 3]);
+        assert_eq!(manager.thumbnail("after").unwrap().unwrap().data, screen().data);
+
+        fs::remove_dir_all(&manager.directory).unwrap();
+    }
+
+    #[test]
+    fn failed_rename_leaves_the_slot_alone() {
+        let manager = manager("rename-failed");
+
+        manager.create("before", &[1, 2, 3], 10, Some(&screen())).unwrap();
+        // The thumbnail can't be moved, after the state already was.
+        fs::remove_file(manager.slot_path("before", "rgba")).unwrap();
+
+        assert!(manager.rename("before", "after").is_err());
+        assert_eq!(files(&manager), vec!["before.json", "before.state"]);
+        assert_eq!(manager.load("before").unwrap().1, vec![1, 2, 3]);
+
+        fs::remove_dir_all(&manager.directory).unwrap();
+    }
+
+    #[test]
+    fn rename_onto_existing_slot() {
+        let manager = manager("rename-existing");
+
+        manager.create("one", &[1], 10, None).unwrap();
+        manager.create("two", &[2], 20, None).unwrap();
+
+        assert!(manager.rename("one", "two").is_err());
+        assert_eq!(manager.load("one").unwrap().1, vec![1]);
+        assert_eq!(manager.load("two").unwrap().1, vec![2]);
+
+        fs::remove_dir_all(&manager.directory).unwrap();
+    }
+}
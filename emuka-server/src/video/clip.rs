@@ -0,0 +1,246 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use eyre::{Report, Result};
+use lazy_static::lazy_static;
+
+use super::{FRAME_RATE_DENOMINATOR, FRAME_RATE_NUMERATOR, MAX_SCALE, scale_pixels, xrgb_to_indexed, xrgb_to_rgba};
+
+/// Longest clip that can be exported.
+pub const MAX_CLIP_SECONDS: f32 = 30.0;
+
+/// Identical consecutive frames are stored once, up to this many times in a row.
+const MAX_REPEAT: u32 = 100;
+
+/// GIF viewers treat delays under 2cs as 10cs, so shorter frames get merged.
+const MIN_GIF_DELAY: u64 = 2;
+
+/// APNG delays are expressed in 1/10000th of a second.
+const APNG_DELAY_DENOMINATOR: u64 = 10000;
+
+#[derive(Debug, Deserialize, Copy, Clone, PartialEq)]
+pub enum ClipFormat {
+    Gif,
+    Apng
+}
+
+impl Default for ClipFormat {
+    fn default() -> Self {
+        Self::Gif
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ClipPixels {
+    /// RGBA palette and one index per pixel.
+    Indexed { palette: Vec<u8>, indices: Vec<u8> },
+    /// Frames with more than 256 colors.
+    Rgba(Vec<u8>)
+}
+
+#[derive(Debug, Clone)]
+struct ClipFrame {
+    width: u32,
+    height: u32,
+    pixels: ClipPixels,
+    /// Number of consecutive emulated frames this frame was shown for.
+    repeat: u32
+}
+
+impl ClipFrame {
+    fn rgba(&self) -> Vec<u8> {
+        match &self.pixels {
+            ClipPixels::Indexed { palette, indices } => {
+                let mut rgba = Vec::with_capacity(indices.len() * 4);
+                for index in indices {
+                    let start = *index as usize * 4;
+                    rgba.extend_from_slice(&palette[start..(start + 4)]);
+                }
+                rgba
+            },
+            ClipPixels::Rgba(rgba) => rgba.clone()
+        }
+    }
+}
+
+struct ClipRing {
+    frames: VecDeque<ClipFrame>,
+    /// Sum of every frame's `repeat`.
+    length: u64,
+    last_source: Vec<u32>
+}
+
+lazy_static! {
+    static ref CLIP_RING: Mutex<ClipRing> = Mutex::new(ClipRing {
+        frames: VecDeque::new(),
+        length: 0,
+        last_source: Vec::new()
+    });
+}
+
+fn seconds_to_frames(seconds: f32) -> u64 {
+    (seconds as f64 * FRAME_RATE_NUMERATOR as f64 / FRAME_RATE_DENOMINATOR as f64).ceil() as u64
+}
+
+/// Start of `frame`, in 1/`denominator`th of a second since the start of the clip.
+fn frame_time(frame: u64, denominator: u64) -> u64 {
+    (frame * FRAME_RATE_DENOMINATOR * denominator + FRAME_RATE_NUMERATOR / 2) / FRAME_RATE_NUMERATOR
+}
+
+pub fn push(data: &[u32], width: u32, height: u32) {
+    let mut ring = CLIP_RING.lock().unwrap();
+    let capacity = seconds_to_frames(MAX_CLIP_SECONDS);
+
+    let repeated = ring.last_source == data && match ring.frames.back() {
+        Some(last) => last.width == width && last.height == height && last.repeat < MAX_REPEAT,
+        None => false
+    };
+
+    if repeated {
+        if let Some(last) = ring.frames.back_mut() {
+            last.repeat = last.repeat + 1;
+        }
+    } else {
+        let pixels = match xrgb_to_indexed(data) {
+            Some((palette, indices)) => ClipPixels::Indexed { palette, indices },
+            None => ClipPixels::Rgba(xrgb_to_rgba(data))
+        };
+
+        ring.frames.push_back(ClipFrame {
+            width,
+            height,
+            pixels,
+            repeat: 1
+        });
+        ring.last_source = Vec::from(data);
+    }
+    ring.length = ring.length + 1;
+
+    while ring.length > capacity {
+        let front = ring.frames.front_mut().unwrap();
+        if front.repeat > 1 {
+            front.repeat = front.repeat - 1;
+        } else {
+            ring.frames.pop_front();
+        }
+        ring.length = ring.length - 1;
+    }
+}
+
+/// Copies the frames making up the last `seconds`, stopping at the first
+/// frame whose size differs from the most recent one.
+fn last_frames(seconds: f32) -> Vec<ClipFrame> {
+    let ring = CLIP_RING.lock().unwrap();
+    let mut remaining = seconds_to_frames(seconds.min(MAX_CLIP_SECONDS));
+    let mut frames = Vec::new();
+
+    let size = match ring.frames.back() {
+        Some(last) => (last.width, last.height),
+        None => return frames
+    };
+
+    for frame in ring.frames.iter().rev() {
+        if remaining == 0 || (frame.width, frame.height) != size {
+            break;
+        }
+
+        let mut frame = frame.clone();
+        frame.repeat = frame.repeat.min(remaining as u32);
+        remaining = remaining - frame.repeat as u64;
+        frames.push(frame);
+    }
+
+    frames.reverse();
+    frames
+}
+
+fn encode_gif(frames: &[ClipFrame], factor: u32) -> Result<Vec<u8>> {
+    let width = frames[0].width * factor;
+    let height = frames[0].height * factor;
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(Report::msg("Clip is too large for a GIF"));
+    }
+
+    let mut output = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut output, width as u16, height as u16, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        let mut start = 0;
+        let mut position = 0;
+        for (index, frame) in frames.iter().enumerate() {
+            position = position + frame.repeat as u64;
+            let delay = frame_time(position, 100) - frame_time(start, 100);
+
+            // Carry too short frames over to the next one, except for the last.
+            if delay < MIN_GIF_DELAY && index + 1 < frames.len() {
+                continue;
+            }
+            start = position;
+
+            let mut gif_frame = match &frame.pixels {
+                ClipPixels::Indexed { palette, indices } => {
+                    let rgb: Vec<u8> = palette.chunks_exact(4).flat_map(|color| color[..3].to_vec()).collect();
+                    let indices = scale_pixels(indices, 1, frame.width, frame.height, factor);
+                    gif::Frame::from_palette_pixels(width as u16, height as u16, &indices, &rgb, None)
+                },
+                ClipPixels::Rgba(_) => {
+                    let mut rgba = scale_pixels(&frame.rgba(), 4, frame.width, frame.height, factor);
+                    gif::Frame::from_rgba_speed(width as u16, height as u16, &mut rgba, 10)
+                }
+            };
+            gif_frame.delay = delay as u16;
+
+            encoder.write_frame(&gif_frame)?;
+        }
+    }
+
+    Ok(output)
+}
+
+fn encode_apng(frames: &[ClipFrame], factor: u32) -> Result<Vec<u8>> {
+    let width = frames[0].width * factor;
+    let height = frames[0].height * factor;
+
+    let mut output = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut output, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(frames.len() as u32, 0)?;
+
+        let mut writer = encoder.write_header()?;
+        let mut position = 0;
+        for frame in frames {
+            let start = position;
+            position = position + frame.repeat as u64;
+            let delay = frame_time(position, APNG_DELAY_DENOMINATOR) - frame_time(start, APNG_DELAY_DENOMINATOR);
+
+            writer.set_frame_delay(delay as u16, APNG_DELAY_DENOMINATOR as u16)?;
+            writer.write_image_data(&scale_pixels(&frame.rgba(), 4, frame.width, frame.height, factor))?;
+        }
+        writer.finish()?;
+    }
+
+    Ok(output)
+}
+
+/// Encodes the last `seconds` of emulated frames, upscaled by `factor`.
+pub fn export(seconds: f32, format: ClipFormat, factor: u32) -> Result<Vec<u8>> {
+    if factor == 0 || factor > MAX_SCALE {
+        return Err(Report::msg(format!("Scale must be between 1 and {}", MAX_SCALE)));
+    }
+
+    if !(seconds > 0.0) {
+        return Err(Report::msg("Clip length must be positive"));
+    }
+
+    let frames = last_frames(seconds);
+    if frames.is_empty() {
+        return Err(Report::msg("No frames to export"));
+    }
+
+    match format {
+        ClipFormat::Gif => encode_gif(&frames, factor),
+        ClipFormat::Apng => encode_apng(&frames, factor)
+    }
+}
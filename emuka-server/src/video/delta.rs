@@ -0,0 +1,103 @@
+use super::stream::VideoFrame;
+
+/// Width and height of the blocks delta frames are made of.
+pub const TILE_SIZE: u32 = 8;
+
+pub fn tile_count(width: u32, height: u32) -> u32 {
+    tiles_per_row(width) * ((height + TILE_SIZE - 1) / TILE_SIZE)
+}
+
+fn tiles_per_row(width: u32) -> u32 {
+    (width + TILE_SIZE - 1) / TILE_SIZE
+}
+
+/// Returns the `(x, y, width, height)` of `tile`, clipped to the frame.
+pub fn tile_bounds(width: u32, height: u32, tile: u16) -> (u32, u32, u32, u32) {
+    let row_tiles = tiles_per_row(width).max(1);
+    let x = (tile as u32 % row_tiles) * TILE_SIZE;
+    let y = (tile as u32 / row_tiles) * TILE_SIZE;
+
+    (x, y, TILE_SIZE.min(width.saturating_sub(x)), TILE_SIZE.min(height.saturating_sub(y)))
+}
+
+/// Lists the tiles of `frame` that differ from `base`. Both frames must have
+/// the same dimensions.
+pub fn changed_tiles(base: &VideoFrame, frame: &VideoFrame) -> Vec<u16> {
+    let width = frame.width as usize;
+
+    (0..tile_count(frame.width, frame.height) as u16)
+        .filter(|tile| {
+            let (x, y, tile_width, tile_height) = tile_bounds(frame.width, frame.height, *tile);
+            (y..(y + tile_height)).any(|row| {
+                let start = row as usize * width + x as usize;
+                let end = start + tile_width as usize;
+                base.data[start..end] != frame.data[start..end]
+            })
+        })
+        .collect()
+}
+
+/// Packs `tiles` out of converted frame `data` as a sequence of
+/// `[tile index: u16 LE][tile pixels, row by row]`.
+pub fn extract_tiles(data: &[u8], bytes_per_pixel: usize, width: u32, height: u32, tiles: &[u16]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let row_length = width as usize * bytes_per_pixel;
+
+    for tile in tiles {
+        let (x, y, tile_width, tile_height) = tile_bounds(width, height, *tile);
+        output.extend_from_slice(&tile.to_le_bytes());
+
+        for row in y..(y + tile_height) {
+            let start = row as usize * row_length + x as usize * bytes_per_pixel;
+            output.extend_from_slice(&data[start..(start + tile_width as usize * bytes_per_pixel)]);
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32, data: Vec<u32>) -> VideoFrame {
+        VideoFrame {
+            number: 0,
+            width,
+            height,
+            data
+        }
+    }
+
+    #[test]
+    fn edge_tiles_are_clipped() {
+        assert_eq!(tile_count(20, 12), 6);
+        assert_eq!(tile_bounds(20, 12, 0), (0, 0, 8, 8));
+        assert_eq!(tile_bounds(20, 12, 2), (16, 0, 4, 8));
+        assert_eq!(tile_bounds(20, 12, 5), (16, 8, 4, 4));
+    }
+
+    #[test]
+    fn finds_changed_tiles() {
+        let base = frame(20, 12, vec![0; 240]);
+        let mut data = vec![0; 240];
+        // (3, 1) is in tile 0, (19, 11) in tile 5.
+        data[20 + 3] = 1;
+        data[11 * 20 + 19] = 1;
+
+        assert_eq!(changed_tiles(&base, &frame(20, 12, data)), vec![0, 5]);
+        assert!(changed_tiles(&base, &base).is_empty());
+    }
+
+    #[test]
+    fn extracts_tile_rows() {
+        let data: Vec<u8> = (0..(20 * 12)).map(|pixel| pixel as u8).collect();
+        let tiles = extract_tiles(&data, 1, 20, 12, &[5]);
+
+        let mut expected = vec![5, 0];
+        for row in 8..12 {
+            expected.extend((16..20).map(|column| (row * 20 + column) as u8));
+        }
+        assert_eq!(tiles, expected);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use crate::emulators::ScreenData;
+
+pub mod clip;
+pub mod delta;
+pub mod recording;
+pub mod screenshot;
+pub mod stream;
+
+/// Largest integer scale factor accepted for exported images.
+pub const MAX_SCALE: u32 = 8;
+
+/// Game Boy frames last 70224 cycles of a 4194304 Hz clock.
+pub const FRAME_RATE_NUMERATOR: u64 = 4194304;
+pub const FRAME_RATE_DENOMINATOR: u64 = 70224;
+
+/// Upscales `bytes_per_pixel` wide pixels by an integer `factor` with nearest-neighbour filtering.
+pub fn scale_pixels(data: &[u8], bytes_per_pixel: usize, width: u32, height: u32, factor: u32) -> Vec<u8> {
+    if factor <= 1 {
+        return data.to_vec();
+    }
+
+    let factor = factor as usize;
+    let row_length = width as usize * bytes_per_pixel;
+
+    let mut scaled = Vec::with_capacity(data.len() * factor * factor);
+    for row in data.chunks_exact(row_length).take(height as usize) {
+        let mut scaled_row = Vec::with_capacity(row_length * factor);
+        for pixel in row.chunks_exact(bytes_per_pixel) {
+            for _ in 0..factor {
+                scaled_row.extend_from_slice(pixel);
+            }
+        }
+
+        for _ in 0..factor {
+            scaled.extend_from_slice(&scaled_row);
+        }
+    }
+
+    scaled
+}
+
+/// Upscales RGBA `screen_data` by an integer `factor` with nearest-neighbour filtering.
+pub fn scale(screen_data: &ScreenData, factor: u32) -> ScreenData {
+    let factor = factor.max(1);
+
+    ScreenData {
+        data: scale_pixels(&screen_data.data, 4, screen_data.width, screen_data.height, factor),
+        width: screen_data.width * factor,
+        height: screen_data.height * factor
+    }
+}
+
+pub fn xrgb_to_rgba(pixels: &[u32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(pixels.len() * 4);
+    for pixel in pixels {
+        bytes.extend_from_slice(&(pixel << 8 | 0xFF).to_be_bytes());
+    }
+    bytes
+}
+
+pub fn xrgb_to_bytes(pixels: &[u32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(pixels.len() * 4);
+    for pixel in pixels {
+        bytes.extend_from_slice(&pixel.to_le_bytes());
+    }
+    bytes
+}
+
+/// Splits `pixels` into an RGBA palette and one palette index per pixel,
+/// or returns `None` if the frame uses more than 256 colors.
+pub fn xrgb_to_indexed(pixels: &[u32]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let mut colors = HashMap::<u32, u8>::new();
+    let mut palette = Vec::new();
+    let mut indices = Vec::with_capacity(pixels.len());
+
+    for pixel in pixels {
+        let color = pixel & 0x00FF_FFFF;
+        let index = match colors.get(&color) {
+            Some(index) => *index,
+            None => {
+                if colors.len() == 256 {
+                    return None;
+                }
+
+                let index = colors.len() as u8;
+                colors.insert(color, index);
+                palette.extend_from_slice(&(color << 8 | 0xFF).to_be_bytes());
+                index
+            }
+        };
+        indices.push(index);
+    }
+
+    Some((palette, indices))
+}
@@ -0,0 +1,214 @@
+use std::{fs::{self, File}, io::{BufWriter, Write}, path::PathBuf, sync::Mutex};
+
+use eyre::{Report, Result};
+use lazy_static::lazy_static;
+
+use crate::audio::{SAMPLE_RATE, StereoSample, wav::WavWriter};
+
+use super::{FRAME_RATE_DENOMINATOR, FRAME_RATE_NUMERATOR};
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+pub enum RecordingFormat {
+    /// YUV4MPEG2 with 4:4:4 chroma, which ffmpeg reads as-is.
+    Y4m,
+    /// Headerless 24-bit RGB frames.
+    Raw
+}
+
+impl Default for RecordingFormat {
+    fn default() -> Self {
+        Self::Y4m
+    }
+}
+
+impl RecordingFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Y4m => "y4m",
+            Self::Raw => "rgb"
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordingSettings {
+    /// Output path without extension; the video and WAV files are written next to each other.
+    pub path: PathBuf,
+    pub format: RecordingFormat
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RecordingSummary {
+    pub video_path: PathBuf,
+    pub audio_path: PathBuf,
+    pub format: RecordingFormat,
+    pub width: u32,
+    pub height: u32,
+    pub frames: u64,
+    pub samples: u64
+}
+
+struct Recording {
+    format: RecordingFormat,
+    video_path: PathBuf,
+    audio_path: PathBuf,
+    video: BufWriter<File>,
+    audio: WavWriter,
+    dimensions: Option<(u32, u32)>,
+    frames: u64,
+    failed: bool
+}
+
+lazy_static! {
+    static ref RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+}
+
+/// Number of samples matching `frames` frames of video.
+fn samples_for_frames(frames: u64) -> u64 {
+    frames * SAMPLE_RATE as u64 * FRAME_RATE_DENOMINATOR / FRAME_RATE_NUMERATOR
+}
+
+/// BT.601 limited range, which is what ffmpeg assumes for Y4M input.
+fn xrgb_to_yuv(pixel: u32) -> (u8, u8, u8) {
+    let r = ((pixel >> 16) & 0xFF) as i32;
+    let g = ((pixel >> 8) & 0xFF) as i32;
+    let b = (pixel & 0xFF) as i32;
+
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+
+    (y as u8, u as u8, v as u8)
+}
+
+impl Recording {
+    fn write_frame(&mut self, data: &[u32], width: u32, height: u32) -> Result<()> {
+        match self.dimensions {
+            Some(dimensions) if dimensions != (width, height) => {
+                return Err(Report::msg(format!("Frame size changed from {:?} to {:?}", dimensions, (width, height))));
+            },
+            Some(_) => (),
+            None => {
+                self.dimensions = Some((width, height));
+                if self.format == RecordingFormat::Y4m {
+                    writeln!(
+                        self.video,
+                        "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+                        width, height, FRAME_RATE_NUMERATOR, FRAME_RATE_DENOMINATOR
+                    )?;
+                }
+            }
+        }
+
+        match self.format {
+            RecordingFormat::Y4m => {
+                let yuv: Vec<(u8, u8, u8)> = data.iter().map(|pixel| xrgb_to_yuv(*pixel)).collect();
+
+                self.video.write_all(b"FRAME\n")?;
+                self.video.write_all(&yuv.iter().map(|(y, _, _)| *y).collect::<Vec<u8>>())?;
+                self.video.write_all(&yuv.iter().map(|(_, u, _)| *u).collect::<Vec<u8>>())?;
+                self.video.write_all(&yuv.iter().map(|(_, _, v)| *v).collect::<Vec<u8>>())?;
+            },
+            RecordingFormat::Raw => {
+                let mut rgb = Vec::with_capacity(data.len() * 3);
+                for pixel in data {
+                    rgb.extend_from_slice(&pixel.to_be_bytes()[1..]);
+                }
+                self.video.write_all(&rgb)?;
+            }
+        }
+
+        self.frames = self.frames + 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<RecordingSummary> {
+        self.video.flush()?;
+        // Pad or trim the audio so both files last exactly as long.
+        let samples = self.audio.finish(Some(samples_for_frames(self.frames)))?;
+        let (width, height) = self.dimensions.unwrap_or((0, 0));
+
+        Ok(RecordingSummary {
+            video_path: self.video_path,
+            audio_path: self.audio_path,
+            format: self.format,
+            width,
+            height,
+            frames: self.frames,
+            samples
+        })
+    }
+}
+
+/// Starts recording. Should be called between two emulated frames so both
+/// files start on a frame boundary.
+pub fn start(settings: RecordingSettings) -> Result<()> {
+    let mut lock = RECORDING.lock().unwrap();
+    if lock.is_some() {
+        return Err(Report::msg("Already recording"));
+    }
+
+    let video_path = settings.path.with_extension(settings.format.extension());
+    let audio_path = settings.path.with_extension("wav");
+
+    if let Some(parent) = video_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    *lock = Some(Recording {
+        format: settings.format,
+        video: BufWriter::new(File::create(&video_path)?),
+        audio: WavWriter::create(&audio_path)?,
+        video_path,
+        audio_path,
+        dimensions: None,
+        frames: 0,
+        failed: false
+    });
+
+    Ok(())
+}
+
+pub fn stop() -> Option<Result<RecordingSummary>> {
+    let recording = RECORDING.lock().unwrap().take()?;
+    let failed = recording.failed;
+    let result = recording.finish();
+
+    // The files are still finished so what was recorded can be played.
+    if failed {
+        return Some(result.and(Err(Report::msg("Writing the recording failed, the files are incomplete"))));
+    }
+
+    Some(result)
+}
+
+pub fn write_frame(data: &[u32], width: u32, height: u32) {
+    let mut lock = RECORDING.lock().unwrap();
+    if let Some(recording) = lock.as_mut() {
+        if recording.failed {
+            return;
+        }
+
+        if let Err(err) = recording.write_frame(data, width, height) {
+            eprintln!("{}", err);
+            recording.failed = true;
+        }
+    }
+}
+
+pub fn write_samples(samples: &[StereoSample]) {
+    let mut lock = RECORDING.lock().unwrap();
+    if let Some(recording) = lock.as_mut() {
+        if recording.failed {
+            return;
+        }
+
+        for sample in samples {
+            if let Err(err) = recording.audio.write(sample) {
+                eprintln!("{}", err);
+                recording.failed = true;
+                return;
+            }
+        }
+    }
+}
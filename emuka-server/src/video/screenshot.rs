@@ -0,0 +1,39 @@
+use std::{fs, path::Path};
+
+use eyre::{Report, Result};
+
+use crate::emulators::ScreenData;
+
+use super::{scale, MAX_SCALE};
+
+/// Encodes RGBA `screen_data` as a PNG, upscaled by `factor`.
+pub fn encode_png(screen_data: &ScreenData, factor: u32) -> Result<Vec<u8>> {
+    if factor == 0 || factor > MAX_SCALE {
+        return Err(Report::msg(format!("Scale must be between 1 and {}", MAX_SCALE)));
+    }
+
+    let scaled = scale(screen_data, factor);
+    let mut output = Vec::new();
+
+    {
+        let mut encoder = png::Encoder::new(&mut output, scaled.width, scaled.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&scaled.data)?;
+    }
+
+    Ok(output)
+}
+
+pub fn save_png(screen_data: &ScreenData, factor: u32, path: &Path) -> Result<()> {
+    let data = encode_png(screen_data, factor)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, data)?;
+    Ok(())
+}
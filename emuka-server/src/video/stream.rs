@@ -0,0 +1,260 @@
+use std::{collections::VecDeque, sync::{Arc, atomic::{AtomicU64, Ordering}}};
+
+use lazy_static::lazy_static;
+use tokio::sync::broadcast;
+
+use super::{delta, xrgb_to_bytes, xrgb_to_indexed, xrgb_to_rgba};
+
+/// A frame as produced by the core, in XRGB8888.
+#[derive(Debug)]
+pub struct VideoFrame {
+    /// Counts every frame the core presented, including unpublished ones.
+    pub number: u64,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u32>
+}
+
+lazy_static! {
+    pub static ref VIDEO_FRAMES: broadcast::Sender<Arc<VideoFrame>> = broadcast::channel(8).0;
+}
+
+static FRAME_NUMBER: AtomicU64 = AtomicU64::new(0);
+
+pub fn publish(data: &[u32], width: u32, height: u32) {
+    let number = FRAME_NUMBER.fetch_add(1, Ordering::Relaxed);
+
+    // Skip the copy entirely while nobody is streaming.
+    if VIDEO_FRAMES.receiver_count() == 0 {
+        return;
+    }
+
+    let frame = VideoFrame {
+        number,
+        width,
+        height,
+        data: Vec::from(data)
+    };
+
+    let _ = VIDEO_FRAMES.send(Arc::new(frame));
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+pub enum VideoFormat {
+    Rgba,
+    Xrgb,
+    Indexed
+}
+
+impl VideoFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Self::Rgba | Self::Xrgb => 4,
+            Self::Indexed => 1
+        }
+    }
+}
+
+impl Default for VideoFormat {
+    fn default() -> Self {
+        Self::Rgba
+    }
+}
+
+#[derive(Debug, Deserialize, Copy, Clone, PartialEq)]
+pub enum VideoCompression {
+    None,
+    Snappy,
+    Deflate
+}
+
+impl Default for VideoCompression {
+    fn default() -> Self {
+        Self::Snappy
+    }
+}
+
+fn default_divisor() -> u32 {
+    1
+}
+
+fn default_keyframe_interval() -> u32 {
+    120
+}
+
+#[derive(Debug, Deserialize, Copy, Clone)]
+pub struct VideoStreamRequest {
+    /// Only every `divisor`-th presented frame is considered for sending.
+    #[serde(default = "default_divisor")]
+    pub divisor: u32,
+    #[serde(default)]
+    pub format: VideoFormat,
+    #[serde(default)]
+    pub compression: VideoCompression,
+    /// Send only the tiles that changed since the last acknowledged frame.
+    #[serde(default)]
+    pub delta: bool,
+    /// Sent frames between two forced keyframes in delta mode.
+    #[serde(default = "default_keyframe_interval")]
+    pub keyframe_interval: u32
+}
+
+/// Messages a client can send on the video stream.
+#[derive(Debug, Deserialize, Copy, Clone)]
+#[serde(untagged)]
+pub enum VideoStreamMessage {
+    /// Acknowledges that the client decoded `frame`, making it the base of
+    /// the next delta frames.
+    Ack { ack: u64 },
+    Configure(VideoStreamRequest)
+}
+
+impl Default for VideoStreamRequest {
+    fn default() -> Self {
+        Self {
+            divisor: default_divisor(),
+            format: VideoFormat::default(),
+            compression: VideoCompression::default(),
+            delta: false,
+            keyframe_interval: default_keyframe_interval()
+        }
+    }
+}
+
+/// Pixels of a frame converted to a client's format.
+#[derive(Debug)]
+pub struct ConvertedFrame {
+    /// May differ from the requested format: indexed frames with more than
+    /// 256 colors fall back to RGBA.
+    pub format: VideoFormat,
+    /// RGBA entries, only filled for indexed frames.
+    pub palette: Vec<u8>,
+    pub data: Vec<u8>
+}
+
+pub fn convert(frame: &VideoFrame, format: VideoFormat) -> ConvertedFrame {
+    let rgba = || ConvertedFrame {
+        format: VideoFormat::Rgba,
+        palette: Vec::new(),
+        data: xrgb_to_rgba(&frame.data)
+    };
+
+    match format {
+        VideoFormat::Rgba => rgba(),
+        VideoFormat::Xrgb => ConvertedFrame {
+            format,
+            palette: Vec::new(),
+            data: xrgb_to_bytes(&frame.data)
+        },
+        VideoFormat::Indexed => match xrgb_to_indexed(&frame.data) {
+            Some((palette, data)) => ConvertedFrame {
+                format,
+                palette,
+                data
+            },
+            None => rgba()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FrameEncoding {
+    Key,
+    Delta { base: u64, tiles: Vec<u16> }
+}
+
+/// Sent frames kept around waiting for the client to acknowledge them.
+const UNACKNOWLEDGED_CAPACITY: usize = 120;
+
+/// Per-client filter deciding which published frames get sent, and how.
+#[derive(Debug)]
+pub struct VideoStreamSubscription {
+    pub request: VideoStreamRequest,
+    last_number: Option<u64>,
+    last_sent: Option<Arc<VideoFrame>>,
+    unacknowledged: VecDeque<Arc<VideoFrame>>,
+    acknowledged: Option<Arc<VideoFrame>>,
+    since_keyframe: u32
+}
+
+impl VideoStreamSubscription {
+    pub fn new(request: VideoStreamRequest) -> Self {
+        Self {
+            request,
+            last_number: None,
+            last_sent: None,
+            unacknowledged: VecDeque::new(),
+            acknowledged: None,
+            since_keyframe: 0
+        }
+    }
+
+    pub fn configure(&mut self, request: VideoStreamRequest) {
+        self.request = request;
+        // Make sure the client gets a full frame in its new format.
+        self.last_sent = None;
+        self.unacknowledged.clear();
+        self.acknowledged = None;
+    }
+
+    pub fn acknowledge(&mut self, number: u64) {
+        if let Some(position) = self.unacknowledged.iter().position(|frame| frame.number == number) {
+            self.acknowledged = self.unacknowledged.get(position).cloned();
+            self.unacknowledged.drain(..=position);
+        }
+    }
+
+    /// Decides how an accepted `frame` is sent: as a keyframe, or as the
+    /// tiles that changed since the last acknowledged frame.
+    pub fn encoding(&mut self, frame: &Arc<VideoFrame>) -> FrameEncoding {
+        if !self.request.delta {
+            return FrameEncoding::Key;
+        }
+
+        if self.unacknowledged.len() >= UNACKNOWLEDGED_CAPACITY {
+            self.unacknowledged.pop_front();
+        }
+        self.unacknowledged.push_back(frame.clone());
+
+        let base = match &self.acknowledged {
+            Some(base) if base.width == frame.width && base.height == frame.height => base.clone(),
+            _ => {
+                self.since_keyframe = 0;
+                return FrameEncoding::Key;
+            }
+        };
+
+        self.since_keyframe = self.since_keyframe + 1;
+        if self.since_keyframe >= self.request.keyframe_interval.max(1) {
+            self.since_keyframe = 0;
+            return FrameEncoding::Key;
+        }
+
+        FrameEncoding::Delta {
+            base: base.number,
+            tiles: delta::changed_tiles(&base, frame)
+        }
+    }
+
+    /// Whether `frame` should be sent, skipping frames that fall between two
+    /// divisor steps and frames identical to the last one sent.
+    pub fn accept(&mut self, frame: &Arc<VideoFrame>) -> bool {
+        let divisor = self.request.divisor.max(1) as u64;
+
+        if let Some(last_number) = self.last_number {
+            if frame.number.saturating_sub(last_number) < divisor {
+                return false;
+            }
+        }
+        self.last_number = Some(frame.number);
+
+        if let Some(last_sent) = &self.last_sent {
+            if last_sent.width == frame.width && last_sent.height == frame.height && last_sent.data == frame.data {
+                return false;
+            }
+        }
+
+        self.last_sent = Some(frame.clone());
+        true
+    }
+}